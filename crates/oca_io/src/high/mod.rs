@@ -1,4 +1,5 @@
 pub mod cbuf;
 pub mod network;
+pub mod rng;
 pub mod string;
 pub mod svec;
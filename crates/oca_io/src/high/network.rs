@@ -3,8 +3,8 @@ use crate::{Error, Result, file::File};
 use super::svec::StaticVec;
 
 #[repr(C)]
-#[derive(Clone, Copy, Default, Debug)]
-pub struct LeaderboardEntry(pub [u8; 3], pub u8);
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct LeaderboardEntry(pub [u8; 3], pub u16);
 
 pub type LeaderboardEntries = [LeaderboardEntry; 10];
 
@@ -0,0 +1,106 @@
+use crate::{Error, Result, sys::random::getrandom};
+
+/// A small, fast and seedable pseudo-random number generator.
+///
+/// This is an implementation of the xoshiro256** algorithm. It is _not_ cryptographically secure,
+/// but it is more than good enough for games. Given the same seed, an [`Rng`] always produces the
+/// same sequence of numbers, which makes it possible to reproduce a run exactly.
+#[derive(Clone)]
+pub struct Rng([u64; 4]);
+
+impl Rng {
+    /// Creates a new [`Rng`] from the given seed.
+    ///
+    /// The 64-bit seed is expanded into the full 256-bit state using the splitmix64 algorithm, as
+    /// recommended by the authors of xoshiro.
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut state = [0u64; 4];
+        let mut i = 0;
+        while i < state.len() {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            state[i] = z ^ (z >> 31);
+            i += 1;
+        }
+
+        Self(state)
+    }
+
+    /// Generates a random seed using the kernel's `getrandom` syscall.
+    pub fn random_seed() -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        // The kernel may fill the buffer in more than one go, so keep asking until it's full.
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match getrandom(&mut bytes[filled..])? {
+                0 => return Err(Error::Other("getrandom returned no bytes")),
+                n => filled += n,
+            }
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Returns the next random 64-bit number.
+    pub const fn next_u64(&mut self) -> u64 {
+        let s = &mut self.0;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Returns a random number in the range `0..n`.
+    ///
+    /// This uses Lemire's multiply-and-shift method (without the rejection step), so the bias is
+    /// negligible for the small ranges used here.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n == 0`.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        // The result is always less than `n`, so it can't be truncated.
+        #[allow(clippy::cast_possible_truncation)]
+        let x = ((u128::from(self.next_u64()) * n as u128) >> 64) as usize;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn same_seed() {
+        let (mut a, mut b) = (Rng::new(1234), Rng::new(1234));
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn different_seed() {
+        let (mut a, mut b) = (Rng::new(1234), Rng::new(1235));
+        assert!((0..100).any(|_| a.next_u64() != b.next_u64()));
+    }
+
+    #[test]
+    fn below() {
+        let mut rng = Rng::new(0);
+        for n in 1..1000 {
+            assert!(rng.below(n) < n);
+        }
+    }
+}
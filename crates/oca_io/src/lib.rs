@@ -46,6 +46,7 @@ pub mod socket {
 pub type Result<T> = core::result::Result<T, error::Error>;
 
 pub use error::Error;
-pub use high::{cbuf::CircularBuffer, string::StaticString, svec::StaticVec};
+pub use high::{cbuf::CircularBuffer, rng::Rng, string::StaticString, svec::StaticVec};
 pub use sys::exit;
 pub use sys::ioctl::get_termsize;
+pub use sys::random::getrandom;
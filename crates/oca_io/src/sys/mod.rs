@@ -5,6 +5,7 @@ use syscall::{SYS_exit, syscall};
 pub mod file;
 pub mod ioctl;
 pub mod poll;
+pub mod random;
 pub mod socket;
 pub mod syscall;
 
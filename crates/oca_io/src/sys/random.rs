@@ -0,0 +1,15 @@
+use super::syscall::{SYS_getrandom, syscall_res};
+use crate::Result;
+
+/// Fill the given buffer with random bytes from the kernel's entropy pool.
+///
+/// This function returns the number of bytes written into `bytes`, which may be less than the
+/// length of the buffer (although for small buffers the kernel always fills them completely).
+pub fn getrandom(bytes: &mut [u8]) -> Result<usize> {
+    syscall_res!(
+        SYS_getrandom,
+        bytes.as_mut_ptr(),
+        bytes.len(),
+        0x0 // flags
+    )
+}
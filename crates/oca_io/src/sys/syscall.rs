@@ -37,6 +37,7 @@ mod x86 {
     pub const SYS_timerfd_create: u64 = 283;
     pub const SYS_timerfd_settime: u64 = 286;
     pub const SYS_signalfd4: u64 = 289;
+    pub const SYS_getrandom: u64 = 318;
 
     /// Syscall on x86-64 Linux.
     ///
@@ -153,8 +154,12 @@ mod x86 {
 mod aarch64 {
     pub const SYS_ioctl: u64 = 29;
     pub const SYS_ppoll: u64 = 73;
+    pub const SYS_timerfd_create: u64 = 85;
+    pub const SYS_timerfd_settime: u64 = 86;
+    pub const SYS_clock_gettime: u64 = 113;
     pub const SYS_signalfd4: u64 = 47;
     pub const SYS_rt_sigprocmask: u64 = 135;
+    pub const SYS_getrandom: u64 = 278;
 
     /// Syscall on aarch64 Linux.
     ///
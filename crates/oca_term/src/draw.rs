@@ -332,8 +332,16 @@ impl Draw for Box<'_> {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum Pixel {
-    Draw { color: Color, bright: bool },
+    Draw {
+        color: Color,
+        bright: bool,
+    },
+    /// A dim, shaded pixel, for things that are only there as a guide.
+    Faint {
+        color: Color,
+    },
     Clear,
 }
 
@@ -342,6 +350,11 @@ impl Pixel {
     pub const fn new(color: Color, bright: bool) -> Self {
         Self::Draw { color, bright }
     }
+
+    #[must_use]
+    pub const fn faint(color: Color) -> Self {
+        Self::Faint { color }
+    }
 }
 
 impl Draw for Pixel {
@@ -359,6 +372,9 @@ impl Draw for Pixel {
                 };
                 draw!(ctx, "\x1B[{}m██\x1B[0m", Color::to_str(&color))?;
             }
+            Self::Faint { color } => {
+                draw!(ctx, "\x1B[2;{}m▒▒\x1B[0m", Color::to_str(&color.fg()))?;
+            }
             Self::Clear => draw!(ctx, "  ")?,
         }
         Ok(())
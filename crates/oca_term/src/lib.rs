@@ -67,16 +67,19 @@ impl Terminal {
         self.term_size
     }
 
-    pub fn process_signals(&mut self) -> Result<bool> {
+    /// Handles a signal, if one has been received. When the terminal is resized, its new size is
+    /// read straight away, so that [`Self::size`] is up to date.
+    pub fn process_signals(&mut self) -> Result<SignalEvent> {
         if oca_io::poll::poll_read_fd(self.signalfd.as_file(), Some(Duration::ZERO))? {
             match self.signalfd.get_signal()? {
-                Signal::Interrupt | Signal::Terminate => return Ok(true),
-                Signal::WindowChange => self.exit_with_error(
-                    "detected that the terminal size changed; this is not supported",
-                ),
+                Signal::Interrupt | Signal::Terminate => return Ok(SignalEvent::Exit),
+                Signal::WindowChange => {
+                    self.term_size = oca_io::get_termsize()?;
+                    return Ok(SignalEvent::Resize);
+                }
             }
         }
-        Ok(false)
+        Ok(SignalEvent::None)
     }
 
     pub fn exit_with_error(&mut self, msg: impl AsRef<str>) -> ! {
@@ -103,6 +106,17 @@ impl Terminal {
     }
 }
 
+/// What happened when [`Terminal::process_signals`] was called.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SignalEvent {
+    /// No signal was received.
+    None,
+    /// The program should exit (because of SIGINT or SIGTERM).
+    Exit,
+    /// The terminal was resized (SIGWINCH).
+    Resize,
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
         self.close();
@@ -1,6 +1,11 @@
 use crate::Terminal;
 use core::time::Duration;
-use oca_io::{Result, timer::Instant};
+use oca_io::{
+    Result,
+    file::File,
+    poll::{PollFd, poll},
+    timer::Instant,
+};
 
 impl Terminal {
     pub fn clear_input(&mut self) -> Result<()> {
@@ -36,6 +41,25 @@ impl Terminal {
         }
     }
 
+    /// Waits until a key is pressed, a signal is received, or `file` has data to read (whichever
+    /// happens first). Keys are read into the keyboard buffer, ready for [`Self::key_iter`], and
+    /// signals are left for [`Self::process_signals`].
+    ///
+    /// Returns `true` if `file` is ready to be read.
+    pub fn wait_with(&mut self, file: &File, timeout: Option<Duration>) -> Result<bool> {
+        let mut fds = [
+            PollFd::new(self.file.as_fd(), PollFd::IN),
+            PollFd::new(self.signalfd.as_file().as_fd(), PollFd::IN),
+            PollFd::new(file.as_fd(), PollFd::IN),
+        ];
+        poll(&mut fds, timeout)?;
+
+        if fds[0].has_read() {
+            self.pollkey(Some(Duration::ZERO))?;
+        }
+        Ok(fds[2].has_read())
+    }
+
     pub fn get_key(&mut self, want_key: impl Fn(Key) -> bool) -> Result<Option<Key>> {
         self.get_key_timeout(Some(Duration::ZERO), want_key)
     }
@@ -94,6 +118,7 @@ impl Terminal {
                     None => Key::Esc,
                     _ => Key::Unknown,
                 },
+                b' ' => Key::Char(b' '),
                 ch @ (b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9') => {
                     Key::Char(ch.to_ascii_lowercase())
                 }
@@ -24,6 +24,12 @@ impl Terminal {
         crate::draw_centered(&mut self.out_buf, object, rect, hoff)
     }
 
+    /// Clears the whole screen.
+    pub fn clear(&mut self) -> Result<()> {
+        write!(self.out_buf, "\x1B[2J")?;
+        Ok(())
+    }
+
     pub fn set_cursor(&mut self, cursor: Option<(u16, u16)>) -> Result<()> {
         match (self.cursor, cursor) {
             (None, None) => panic!("didn't actually change cursor"),
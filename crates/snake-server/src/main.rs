@@ -1,12 +1,12 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
 
-use core::slice;
 use std::{
+    collections::BTreeMap,
     env, fs, iter,
-    mem::ManuallyDrop,
     net::{Ipv4Addr, TcpListener, TcpStream},
     os::fd::AsRawFd,
+    path::Path,
 };
 
 use oca_io::{
@@ -15,6 +15,9 @@ use oca_io::{
     poll::PollFd,
 };
 
+/// Leaderboard files start with these bytes. Older files (without them) hold one-byte scores.
+const FILE_MAGIC: &[u8; 4] = b"SLB2";
+
 fn exit_with_error(err: &'static str) -> ! {
     println!("\x1B[91;1merror\x1B[0m: {err}");
     exit(-1)
@@ -27,21 +30,22 @@ macro_rules! info {
 }
 
 fn main() -> Result<()> {
-    // Try to find a leaderboard file
-    let mut leaderboard = fs::read("leaderboard")
-        .ok()
-        .map(|lb| {
-            let mut lb = ManuallyDrop::new(lb);
-            if lb.len() % 4 != 0 {
-                exit_with_error("could not read `leaderboard` file")
-            }
-
-            info!("using `leaderboard` file with {} entries", lb.len() / 4);
-
-            unsafe { Vec::from_raw_parts(lb.as_mut_ptr().cast(), lb.len() / 4, lb.capacity() / 4) }
-        })
-        // If there is no leaderboard file, that's OK: use an empty leaderboard.
-        .unwrap_or_default();
+    // Try to find the leaderboard files. There is one file for each leaderboard: `leaderboard` for
+    // the classic game and `leaderboard-<id>` for every other game mode.
+    let mut leaderboards = BTreeMap::new();
+    for file in fs::read_dir(".").unwrap() {
+        let file = file.unwrap();
+        let name = file.file_name();
+        let Some(board) = name.to_str().and_then(board_from_file_name) else {
+            continue;
+        };
+
+        let lb = read_leaderboard(&file.path());
+        info!("using `{}` file with {} entries", name.display(), lb.len());
+        leaderboards.insert(board, lb);
+    }
+    // If there is no leaderboard file, that's OK: use an empty leaderboard.
+    leaderboards.entry(0).or_default();
 
     let server = TcpListener::bind((
         Ipv4Addr::UNSPECIFIED,
@@ -70,7 +74,9 @@ fn main() -> Result<()> {
             let (stream, _addr) = server.accept().unwrap();
             let mut client = GameClient::new(stream)?;
 
-            client.send_leaderboard(&leaderboard)?;
+            for (&board, leaderboard) in &leaderboards {
+                client.send_leaderboard(board, leaderboard)?;
+            }
             poll_fds.push(PollFd::new(
                 client.stream.as_raw_fd(),
                 PollFd::IN | PollFd::RDHUP,
@@ -95,15 +101,12 @@ fn main() -> Result<()> {
             }
 
             assert!(poll_fd.is_read());
-            client.handle_packet(&mut leaderboard).unwrap();
-
-            let bytes = unsafe {
-                slice::from_raw_parts(leaderboard.as_ptr().cast(), leaderboard.len() * 4)
-            };
-            fs::write("leaderboard", bytes).unwrap();
+            let board = client.handle_packet(&mut leaderboards).unwrap();
+            let leaderboard = &leaderboards[&board];
+            write_leaderboard(&board_file_name(board), leaderboard);
 
             for i in 0..clients.len() {
-                if let Err(_err) = clients[i].send_leaderboard(&leaderboard) {
+                if let Err(_err) = clients[i].send_leaderboard(board, leaderboard) {
                     println!("{}: DISCONNECT (failed packet write)", clients[i].hostname);
                     clients.remove(i);
                     poll_fds.remove(i + 1);
@@ -113,6 +116,59 @@ fn main() -> Result<()> {
     }
 }
 
+/// Returns the name of the file that stores the given leaderboard.
+fn board_file_name(board: u8) -> String {
+    if board == 0 {
+        "leaderboard".to_string()
+    } else {
+        format!("leaderboard-{board}")
+    }
+}
+
+/// The inverse of [`board_file_name`].
+fn board_from_file_name(name: &str) -> Option<u8> {
+    match name {
+        "leaderboard" => Some(0),
+        _ => name.strip_prefix("leaderboard-")?.parse().ok(),
+    }
+}
+
+/// Reads a leaderboard file. Each entry is the player's name followed by their score, as a
+/// big-endian `u16`. Files from before scores could be larger than 255 have a one-byte score and
+/// no [`FILE_MAGIC`]; these are converted the next time the leaderboard is written.
+fn read_leaderboard(path: &Path) -> Vec<LeaderboardEntry> {
+    let lb = fs::read(path).unwrap();
+    let (entries, entry_len) = match lb.strip_prefix(FILE_MAGIC) {
+        Some(entries) => (entries, 5),
+        None => (&lb[..], 4),
+    };
+    if !entries.len().is_multiple_of(entry_len) {
+        exit_with_error("could not read leaderboard file")
+    }
+
+    entries
+        .chunks(entry_len)
+        .map(|entry| {
+            let score = match entry[3..] {
+                [score] => score.into(),
+                [hi, lo] => u16::from_be_bytes([hi, lo]),
+                _ => unreachable!(),
+            };
+            LeaderboardEntry(entry[0..3].try_into().unwrap(), score)
+        })
+        .collect()
+}
+
+/// Writes a leaderboard file, in the format read by [`read_leaderboard`].
+fn write_leaderboard(path: &str, leaderboard: &[LeaderboardEntry]) {
+    let mut bytes = FILE_MAGIC.to_vec();
+    for entry in leaderboard {
+        bytes.extend_from_slice(&entry.0);
+        bytes.extend_from_slice(&entry.1.to_be_bytes());
+    }
+    fs::write(path, bytes).unwrap();
+}
+
 pub struct GameClient {
     stream: TcpStream,
     #[allow(unused)]
@@ -131,13 +187,21 @@ impl GameClient {
         Ok(Self { stream, hostname })
     }
 
-    pub fn handle_packet(&mut self, leaderboard: &mut Vec<LeaderboardEntry>) -> Result<()> {
+    /// Reads a game from the client and inserts it into the correct leaderboard. Returns the
+    /// leaderboard that was changed.
+    pub fn handle_packet(
+        &mut self,
+        leaderboards: &mut BTreeMap<u8, Vec<LeaderboardEntry>>,
+    ) -> Result<u8> {
         let (id, packet) = read_packet(&mut oca_io::file::File::from_fd(self.stream.as_raw_fd()))?;
 
         assert_eq!(id, 0x1);
-        assert_eq!(packet.len(), 4);
+        assert_eq!(packet.len(), 6);
 
-        let game = LeaderboardEntry(packet[0..3].try_into().unwrap(), packet[3]);
+        let score = u16::from_be_bytes(packet[3..5].try_into().unwrap());
+        let game = LeaderboardEntry(packet[0..3].try_into().unwrap(), score);
+        let board = packet[5];
+        let leaderboard = leaderboards.entry(board).or_default();
         let pos = leaderboard
             .binary_search_by(|LeaderboardEntry(_, score)| game.1.cmp(score))
             .map_or_else(|e| e, |e| e + 1);
@@ -145,21 +209,25 @@ impl GameClient {
         leaderboard.insert(pos, game);
 
         let name = std::str::from_utf8(&game.0).unwrap();
-        println!("{}: GAME {} {}", self.hostname, name, game.1);
-        Ok(())
+        println!(
+            "{}: GAME {} {} (board {board})",
+            self.hostname, name, game.1
+        );
+        Ok(board)
     }
 
-    pub fn send_leaderboard(&mut self, leaderboard: &[LeaderboardEntry]) -> Result<()> {
-        let mut lb_packet = [0u8; 40];
+    pub fn send_leaderboard(&mut self, board: u8, leaderboard: &[LeaderboardEntry]) -> Result<()> {
+        let mut lb_packet = [0u8; 51];
+        lb_packet[0] = board;
         for (idx, entry) in leaderboard
             .iter()
             .chain(iter::repeat(&LeaderboardEntry(*b"---", 0)))
             .take(10)
             .enumerate()
         {
-            let idx = idx * 4;
+            let idx = idx * 5 + 1;
             lb_packet[idx..(idx + 3)].copy_from_slice(&entry.0);
-            lb_packet[idx + 3] = entry.1;
+            lb_packet[(idx + 3)..(idx + 5)].copy_from_slice(&entry.1.to_be_bytes());
         }
 
         write_packet(
@@ -33,8 +33,27 @@ fn main() {
         .unwrap();
     }
 
+    // Levels are copied as-is, and we generate a list of them (sorted by file name) so that the
+    // game can include every level without knowing their names.
+    let levels_dir = Path::new(&out_dir).join("levels");
+    fs::create_dir_all(&levels_dir).unwrap();
+    let mut levels = fs::read_dir("levels/")
+        .unwrap()
+        .map(|file| file.unwrap().file_name())
+        .collect::<Vec<_>>();
+    levels.sort();
+    let mut level_list = String::from("&[\n");
+    for name in levels {
+        let dest_path = levels_dir.join(&name);
+        fs::copy(Path::new("levels/").join(&name), &dest_path).unwrap();
+        level_list.push_str(&format!("    include_str!({:?}),\n", dest_path));
+    }
+    level_list.push(']');
+    fs::write(Path::new(&out_dir).join("levels.rs"), level_list).unwrap();
+
     println!("cargo::rerun-if-changed=.git/HEAD");
     println!("cargo::rerun-if-changed=pansi/");
+    println!("cargo::rerun-if-changed=levels/");
 }
 
 #[must_use]
@@ -62,8 +81,10 @@ pub fn from_pansi(s: &str) -> String {
                     "BLUE" => new.push_str(Color::to_str(&Color::Blue.fg())),
                     "WHITE" => new.push_str(Color::to_str(&Color::White.fg())),
 
+                    "BRED" => new.push_str(Color::to_str(&Color::Red.fg_bright())),
                     "BYELLOW" => new.push_str(Color::to_str(&Color::Yellow.fg_bright())),
                     "BGREEN" => new.push_str(Color::to_str(&Color::Green.fg_bright())),
+                    "BBLUE" => new.push_str(Color::to_str(&Color::Blue.fg_bright())),
                     "BCYAN" => new.push_str(Color::to_str(&Color::Cyan.fg_bright())),
 
                     // formats
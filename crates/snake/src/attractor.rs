@@ -1,108 +1,158 @@
-use std::thread;
+use core::time::Duration;
 
-use oca_io::{CircularBuffer, Result};
-use oca_term::{Color, Key, Pixel};
+use oca_io::{Result, Rng, timer::Instant};
+use oca_term::{Draw as _, Key, Pixel, Popup, SignalEvent};
+
+use snakegame::{
+    controller::{Autopilot, Controller as _},
+    game::{GameConfig, GameState},
+    level::Level,
+};
 
 use crate::{
-    snake::{self, Direction},
-    ui::{Coord, GameUi},
+    menu::Menu,
+    snake::{canvas_pixels, event_pixels},
+    ui::GameUi,
 };
 
-pub fn run(ui: &mut GameUi) -> Result<bool> {
-    let mut head = snake::STARTING_POS;
-    let mut tail = CircularBuffer::<Coord, { snake::STARTING_LENGTH * 2 + 1 }>::new();
-    let (mut dir, mut left) = get_dir(0, &mut head).unwrap();
-    let mut move_segment = 0;
+/// How long the menu is left alone before the attractor animation starts.
+const IDLE_TIME: Duration = Duration::from_secs(5);
+/// How often keys and signals are checked while the animation isn't running.
+const MENU_TICK: Duration = Duration::from_millis(50);
+/// How long a finished game is left on the canvas before the next one starts.
+const GAME_OVER_TIME: Duration = Duration::from_secs(1);
 
-    ui.draw_canvas(Coord { x: 24, y: 8 }, Pixel::new(Color::Yellow, true))?;
+/// The attractor animation: the [`autopilot`](snakegame::autopilot) playing real games on the
+/// classic level, while the welcome screen is shown. Each game is different, and a new one starts
+/// when the last one ends.
+pub struct Attractor {
+    /// The game being played, once the animation has started.
+    state: Option<GameState>,
+}
 
-    loop {
-        ui.draw_canvas(head, Pixel::new(Color::Green, true))?;
-        tail.push(head);
+impl Attractor {
+    /// Creates the attractor animation. Nothing is drawn until [`Self::run`] is called.
+    pub const fn new() -> Self {
+        Self { state: None }
+    }
 
-        if tail.len() > snake::STARTING_LENGTH * 2 {
-            ui.draw_canvas(tail.pop().unwrap(), Pixel::Clear)?;
-        }
+    /// Draws the welcome popup with the [`Menu`] in it, and lets the player choose a game. Once the
+    /// menu has been left alone for [`IDLE_TIME`], the attractor animation runs on the canvas
+    /// around it, until the next key is pressed. The animation only carries on from where it was
+    /// while the menu is open: the canvas is reused for the chosen game, so the caller should
+    /// create a new `Attractor` for each visit to the menu.
+    ///
+    /// The popup is treated as a wall, so that the games are played where they can be seen. It is
+    /// redrawn if the terminal is resized. The UI shows the rules (and leaderboard) of the
+    /// highlighted game.
+    ///
+    /// Returns the rules for the chosen game and where the popup was drawn, or [`None`] if the
+    /// program should exit (because of SIGINT or SIGTERM).
+    pub fn run(
+        &mut self,
+        ui: &mut GameUi,
+        menu: &mut Menu,
+    ) -> Result<Option<(GameConfig, (u16, u16))>> {
+        ui.set_config(menu.config())?;
+        let text = menu.text()?;
+        let size = (&Popup::new(&text)).size();
+        let mut pos = ui.draw_centered(&Popup::new(&text), false)?;
+        ui.flush()?;
+        let mut last_key = Instant::now()?;
+        let mut next_step = Instant::now()?;
 
-        let fruit_coord = match head {
-            Coord { x: 24, y: 8 } => Some(Coord { x: 17, y: 4 }),
-            Coord { x: 17, y: 4 } => Some(Coord { x: 6, y: 15 }),
-            Coord { x: 6, y: 15 } => Some(Coord { x: 24, y: 8 }),
-            _ => None,
-        };
-        if let Some(fruit) = fruit_coord {
-            ui.draw_canvas(fruit, Pixel::new(Color::Yellow, true))?;
-        }
+        loop {
+            // The animation steps on its own schedule, so keys and signals that arrive in between
+            // steps don't hurry it along.
+            let now = Instant::now()?;
+            let wait = if last_key.elapsed()? < IDLE_TIME {
+                MENU_TICK
+            } else {
+                if now >= next_step {
+                    next_step = now + self.step(ui, pos, size)?;
+                }
+                next_step - now
+            };
+            ui.flush()?;
 
-        ui.flush()?;
-        thread::sleep(snake::STARTING_STEP_TIME);
+            if let Some(key) = ui.term().get_key_timeout(Some(wait), |k| {
+                matches!(k, Key::Enter | Key::Up | Key::Down | Key::Left | Key::Right)
+            })? {
+                if menu.press(key) {
+                    return Ok(Some((menu.config(), pos)));
+                }
+                ui.set_config(menu.config())?;
+                pos = ui.draw_centered(&Popup::new(&menu.text()?), false)?;
+                last_key = Instant::now()?;
+            }
 
-        ui.draw_canvas(head, Pixel::new(Color::Green, false))?;
+            // The canvas is redrawn after a resize, but the popup is up to us.
+            match ui.update_tick(false)? {
+                SignalEvent::None => (),
+                SignalEvent::Exit => return Ok(None),
+                SignalEvent::Resize => pos = ui.draw_centered(&Popup::new(&menu.text()?), false)?,
+            }
+        }
+    }
 
-        left -= 1;
-        match dir {
-            Direction::Up => head.y -= 1,
-            Direction::Down => head.y += 1,
-            Direction::Right => head.x += 1,
-            Direction::Left => head.x -= 1,
+    /// Steps the animation once, starting a new game if there isn't one (or the last one is
+    /// over). Returns how long to wait before the next step.
+    fn step(&mut self, ui: &mut GameUi, pos: (u16, u16), size: (u16, u16)) -> Result<Duration> {
+        // A finished game has been left on the canvas for a moment; clear it to start another.
+        if let Some(state) = &self.state
+            && state.is_finished()
+        {
+            for (coord, _) in canvas_pixels(state) {
+                if !ui.canvas_covered(coord, pos, size) {
+                    ui.draw_canvas(coord, Pixel::Clear)?;
+                }
+            }
+            self.state = None;
         }
 
-        if left == 0 {
-            move_segment += 1;
-            (dir, left) = get_dir(move_segment, &mut head).unwrap_or_else(|| {
-                move_segment = 0;
-                get_dir(move_segment, &mut head).unwrap()
-            });
+        if self.state.is_none() {
+            let state = new_game(ui, pos, size)?;
+            for (coord, pixel) in canvas_pixels(&state) {
+                if !ui.canvas_covered(coord, pos, size) {
+                    ui.draw_canvas(coord, pixel)?;
+                }
+            }
+            self.state = Some(state);
         }
+        let state = self.state.as_mut().unwrap();
 
-        if ui.term().get_key(|k| k == Key::Enter)?.is_some() {
-            return Ok(false);
+        let input = Autopilot.next(state, 0);
+        for event in state.step(&[input]) {
+            for (coord, pixel) in event_pixels(event) {
+                if !ui.canvas_covered(coord, pos, size) {
+                    ui.draw_canvas(coord, pixel)?;
+                }
+            }
         }
 
-        if ui.update_tick(false)? {
-            return Ok(true);
+        if state.is_finished() {
+            Ok(GAME_OVER_TIME)
+        } else {
+            Ok(state.step_time())
         }
     }
 }
 
-pub const fn get_dir(move_segment: u8, head: &mut Coord) -> Option<(Direction, u8)> {
-    #[allow(clippy::match_same_arms)]
-    Some(match move_segment {
-        0 => (Direction::Up, 4),
-        1 => (Direction::Right, 22),
-        2 => (Direction::Down, 8),
-        3 => (Direction::Left, 2),
-        4 => (Direction::Up, 7),
-        5 => (Direction::Right, 1),
-        6 => (Direction::Down, 10),
-        7 => (Direction::Left, 13),
-        8 => (Direction::Up, 3),
-        9 => (Direction::Right, 3),
-        10 => (Direction::Down, 1),
-        11 => (Direction::Left, 2),
-        12 => (Direction::Down, 1),
-        13 => (Direction::Right, 5),
-        14 => (Direction::Up, 4),
-        15 => {
-            head.y -= 5;
-            (Direction::Up, 2)
-        }
-        16 => (Direction::Right, 6),
-        17 => (Direction::Down, 5),
-        18 => (Direction::Left, 3),
-        19 => {
-            head.x -= 14;
-            (Direction::Left, 2)
-        }
-        20 => (Direction::Down, 9),
-        21 => (Direction::Right, 1),
-        22 => (Direction::Up, 4),
-        23 => (Direction::Right, 1),
-        24 => (Direction::Down, 4),
-        25 => (Direction::Right, 1),
-        26 => (Direction::Up, 5),
-        27 => (Direction::Left, 4),
-        28 => (Direction::Up, 4),
-        _ => return None,
-    })
+/// Starts a new game for the autopilot to play on the classic level, with a random seed. Any part
+/// of the canvas covered by the popup (see [`Attractor::run`]) becomes a wall.
+fn new_game(ui: &GameUi, pos: (u16, u16), size: (u16, u16)) -> Result<GameState> {
+    let covered = ui
+        .size()
+        .coords()
+        .filter(|&coord| ui.canvas_covered(coord, pos, size));
+
+    let mut level = Level::classic().fit(ui.size());
+    level.walls.extend(covered);
+    level.fruits.retain(|fruit| !level.walls.contains(fruit));
+
+    Ok(GameState::new(
+        Rng::random_seed()?,
+        GameConfig::default(),
+        &level,
+    ))
 }
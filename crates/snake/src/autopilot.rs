@@ -0,0 +1,297 @@
+//! A computer player for Snake.
+//!
+//! The autopilot looks at a [`GameState`] and chooses which way a snake should go next, playing
+//! by exactly the same rules as a person. It is used by the attractor on the welcome screen.
+//!
+//! Each step, the autopilot searches (breadth-first) for the shortest path to the nearest fruit.
+//! Before taking that path, it checks that the snake won't trap itself: after eating the fruit,
+//! the snake must still be able to reach its own tail, because following the tail is always safe.
+//! If the path isn't safe (or there isn't one), then the snake follows its tail instead, and
+//! failing that, it heads for whichever neighbouring cell has the most room.
+//!
+//! Following the tail means heading for a free cell next to the end of the tail, never the end of
+//! the tail itself: a snake collides with its tail before the tail moves up (see
+//! [`GameState::step`]), so the end of the tail is only free once the snake has moved on.
+
+use std::collections::VecDeque;
+
+use crate::game::{CanvasSize, Coord, Direction, GameState, Portal};
+
+/// Every direction, in the order that they are tried.
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// The cells of the canvas that a snake can't move into.
+#[derive(Clone)]
+struct Blocked {
+    /// Whether each cell is blocked, indexed by [`Coord::as_idx`].
+    cells: Vec<bool>,
+    size: CanvasSize,
+    /// Whether the snake wraps around at the edges of the canvas.
+    wrap: bool,
+    /// The portals that the snake can go through.
+    portals: Vec<Portal>,
+}
+
+impl Blocked {
+    fn get(&self, coord: Coord) -> bool {
+        self.cells[coord.as_idx(self.size)]
+    }
+
+    fn set(&mut self, coord: Coord, blocked: bool) {
+        self.cells[coord.as_idx(self.size)] = blocked;
+    }
+
+    /// Returns the neighbouring cell in the given direction, following the game's rules for the
+    /// edge of the canvas and for portals.
+    fn neighbour(&self, coord: Coord, dir: Direction) -> Option<Coord> {
+        coord.neighbour(dir, self.size, self.wrap, &self.portals)
+    }
+}
+
+/// Chooses the direction that the given snake should move in next, or [`None`] if it should keep
+/// going the way it is (which is also the answer when every direction is hopeless).
+#[must_use]
+pub fn choose(state: &GameState, snake: usize) -> Option<Direction> {
+    let me = &state.snakes()[snake];
+    let head = me.head();
+    // The snake can't turn back on itself.
+    let back = me.direction().opposite();
+
+    let mut blocked = Blocked {
+        cells: vec![false; state.size().cells()],
+        size: state.size(),
+        wrap: state.config().wrap,
+        portals: state.portals().to_vec(),
+    };
+    for &coord in state.walls() {
+        blocked.set(coord, true);
+    }
+    for other in state.snakes() {
+        for coord in other.tail() {
+            blocked.set(coord, true);
+        }
+    }
+
+    // Take the shortest path to a fruit, as long as the snake can still reach its tail afterwards.
+    let is_fruit = |coord: Coord| state.fruits().iter().any(|fruit| fruit.coord == coord);
+    if let Some(path) = search(&blocked, head, back, is_fruit)
+        && can_reach_tail(state, snake, &blocked, &path)
+    {
+        return Some(path[0].0);
+    }
+
+    // Otherwise, chase the end of the tail, which moves out of the way as the snake moves.
+    let tail_end = me.tail().next().unwrap();
+    if tail_end != head
+        && let Some(path) = search(&blocked, head, back, |coord| {
+            beside_tail_end(&blocked, coord, tail_end)
+        })
+    {
+        return Some(path[0].0);
+    }
+
+    // Otherwise, go wherever there is the most room, and hope for the best.
+    DIRECTIONS
+        .into_iter()
+        .filter(|&dir| dir != back)
+        .filter_map(|dir| Some((dir, blocked.neighbour(head, dir)?)))
+        .filter(|&(_, coord)| !blocked.get(coord))
+        .max_by_key(|&(_, coord)| room(&blocked, coord))
+        .map(|(dir, _)| dir)
+}
+
+/// Checks whether, after following `path` (and eating the fruit at the end of it), the snake
+/// could still reach a cell next to the end of its tail.
+fn can_reach_tail(
+    state: &GameState,
+    snake: usize,
+    blocked: &Blocked,
+    path: &[(Direction, Coord)],
+) -> bool {
+    let me = &state.snakes()[snake];
+    let mut body: VecDeque<_> = me.tail().collect();
+    let mut blocked = blocked.clone();
+    for coord in me.tail() {
+        blocked.set(coord, false);
+    }
+
+    // Move a pretend snake along the path. It grows by one when it eats the fruit at the end.
+    for (idx, &(_, coord)) in path.iter().enumerate() {
+        body.push_back(coord);
+        if idx + 1 < path.len() {
+            body.pop_front();
+        }
+    }
+    for &coord in &body {
+        blocked.set(coord, true);
+    }
+
+    let head = *body.back().unwrap();
+    let tail_end = *body.front().unwrap();
+    let back = path.last().unwrap().0.opposite();
+    search(&blocked, head, back, |coord| {
+        beside_tail_end(&blocked, coord, tail_end)
+    })
+    .is_some()
+}
+
+/// Returns `true` if `coord` is a free cell next to the end of the tail, which is where a snake
+/// following its tail heads for. The snake can move into the end of the tail from there once it
+/// has moved out of the way.
+fn beside_tail_end(blocked: &Blocked, coord: Coord, tail_end: Coord) -> bool {
+    !blocked.get(coord)
+        && DIRECTIONS
+            .into_iter()
+            .any(|dir| blocked.neighbour(coord, dir) == Some(tail_end))
+}
+
+/// Searches breadth-first from `start` for the nearest cell matching `is_goal`, without turning
+/// straight back (in direction `back`) on the first move. The goal cell itself may be blocked.
+///
+/// Returns the path to the goal as the direction of each move and the cell it moves into, or
+/// [`None`] if no goal can be reached.
+fn search(
+    blocked: &Blocked,
+    start: Coord,
+    back: Direction,
+    is_goal: impl Fn(Coord) -> bool,
+) -> Option<Vec<(Direction, Coord)>> {
+    // For each cell that has been visited, the move that reached it, and where that move was from.
+    let size = blocked.size;
+    let mut from: Vec<Option<(Direction, Coord)>> = vec![None; size.cells()];
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(coord) = queue.pop_front() {
+        for dir in DIRECTIONS {
+            if coord == start && dir == back {
+                continue;
+            }
+            let Some(next) = blocked.neighbour(coord, dir) else {
+                continue;
+            };
+            if next == start || from[next.as_idx(size)].is_some() {
+                continue;
+            }
+
+            from[next.as_idx(size)] = Some((dir, coord));
+            if is_goal(next) {
+                // Walk back to the start to find the path.
+                let mut path = vec![(dir, next)];
+                let mut prev = coord;
+                while prev != start {
+                    let (dir, before) = from[prev.as_idx(size)].unwrap();
+                    path.push((dir, prev));
+                    prev = before;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            if !blocked.get(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+/// Counts the empty cells that can be reached from `start`.
+fn room(blocked: &Blocked, start: Coord) -> usize {
+    let mut seen = blocked.clone();
+    seen.set(start, true);
+    let mut queue = VecDeque::from([start]);
+    let mut count = 0;
+
+    while let Some(coord) = queue.pop_front() {
+        count += 1;
+        for dir in DIRECTIONS {
+            if let Some(next) = seen.neighbour(coord, dir)
+                && !seen.get(next)
+            {
+                seen.set(next, true);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::choose;
+    use crate::{
+        game::{CanvasSize, GameConfig, GameMode, GameState},
+        level,
+    };
+
+    /// Lets the autopilot play a whole game, returning its score.
+    fn play(seed: u64, config: GameConfig, level: &level::Level) -> usize {
+        let mut state = GameState::new(seed, config, level);
+        for _ in 0..5000 {
+            if state.is_finished() {
+                break;
+            }
+            let input = choose(&state, 0);
+            state.step(&[input]);
+        }
+        state.score()
+    }
+
+    #[test]
+    fn never_runs_into_tail_end() {
+        // The end of the tail only moves out of the way after the head has moved, so moving into
+        // it is always fatal.
+        let level = level::Level::classic();
+        for seed in 0..50 {
+            let mut state = GameState::new(seed, GameConfig::default(), &level);
+            for _ in 0..5000 {
+                if state.is_finished() {
+                    break;
+                }
+                let input = choose(&state, 0);
+                let me = &state.snakes()[0];
+                let dir = input.unwrap_or_else(|| me.direction());
+                let next = me.head().neighbour(dir, state.size(), false, &[]);
+                let tail_end = me.tail().next().unwrap();
+                assert!(
+                    tail_end == me.head() || next != Some(tail_end),
+                    "seed {seed}, tick {}",
+                    state.ticks()
+                );
+                state.step(&[input]);
+            }
+        }
+    }
+
+    #[test]
+    fn plays_well() {
+        for level in level::bundled() {
+            for wrap in [false, true] {
+                let config = GameConfig {
+                    wrap,
+                    ..GameConfig::default()
+                };
+                let score = play(1, config, &level);
+                assert!(score >= 30, "{} (wrap: {wrap}): {score}", level.name);
+            }
+
+            let config = GameConfig {
+                mode: GameMode::Portals,
+                ..GameConfig::default()
+            };
+            let score = play(1, config, &level);
+            assert!(score >= 30, "{} (portals): {score}", level.name);
+
+            // The autopilot also copes with a bigger canvas.
+            let score = play(1, GameConfig::default(), &level.fit(CanvasSize::MAX));
+            assert!(score >= 30, "{} (big canvas): {score}", level.name);
+        }
+    }
+}
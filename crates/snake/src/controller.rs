@@ -0,0 +1,239 @@
+//! Sources of input for the snakes.
+//!
+//! Each snake in a game is steered by a [`Controller`], which chooses whether (and which way) the
+//! snake turns on every step. The game itself doesn't care where its input comes from: a snake
+//! can be steered from the keyboard, by a script (such as a replay), or by the [`autopilot`].
+
+use oca_term::Key;
+
+use crate::{
+    autopilot,
+    game::{Direction, GameConfig, GameState, InputQueue},
+};
+
+/// Something that steers a snake.
+pub trait Controller {
+    /// Offers a key that has just been pressed to the controller of the given snake. Returns
+    /// `true` if the key belongs to this controller, in which case it isn't offered to any others.
+    fn key(&mut self, state: &GameState, snake: usize, key: Key) -> bool {
+        let _ = (state, snake, key);
+        false
+    }
+
+    /// Chooses the direction that the given snake should turn on the next step, or [`None`] if it
+    /// should keep going the way it is.
+    fn next(&mut self, state: &GameState, snake: usize) -> Option<Direction>;
+}
+
+/// Asks the controller of each snake for its input, to be given to [`GameState::step`].
+pub fn inputs(
+    controllers: &mut [Box<dyn Controller + '_>],
+    state: &GameState,
+) -> Vec<Option<Direction>> {
+    controllers
+        .iter_mut()
+        .enumerate()
+        .map(|(snake, controller)| controller.next(state, snake))
+        .collect()
+}
+
+/// Creates a [`Keyboard`] controller for each player in a game with the given rules.
+#[must_use]
+pub fn keyboards(config: GameConfig) -> Vec<Box<dyn Controller>> {
+    if config.two_player {
+        vec![
+            Box::new(Keyboard::for_player(0)),
+            Box::new(Keyboard::for_player(1)),
+        ]
+    } else {
+        vec![Box::new(Keyboard::new())]
+    }
+}
+
+/// Which keys a [`Keyboard`] controller listens to.
+#[derive(Debug, Clone, Copy)]
+enum Keys {
+    /// Every key.
+    All,
+    /// Every key except the arrow keys.
+    Letters,
+    /// Only the arrow keys.
+    Arrows,
+}
+
+/// Steers a snake with the keyboard, using the arrow keys or WASD.
+pub struct Keyboard {
+    queue: InputQueue,
+    keys: Keys,
+}
+
+impl Keyboard {
+    /// Creates a controller which listens to every key.
+    #[must_use]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            queue: InputQueue::new(),
+            keys: Keys::All,
+        }
+    }
+
+    /// Creates a controller for one player of a two-player game, where the first player steers
+    /// with WASD and the second player steers with the arrow keys.
+    #[must_use]
+    pub fn for_player(player: usize) -> Self {
+        Self {
+            queue: InputQueue::new(),
+            keys: if player == 0 {
+                Keys::Letters
+            } else {
+                Keys::Arrows
+            },
+        }
+    }
+}
+
+impl Controller for Keyboard {
+    fn key(&mut self, state: &GameState, snake: usize, key: Key) -> bool {
+        let is_arrow = matches!(key, Key::Up | Key::Down | Key::Right | Key::Left);
+        let mine = match self.keys {
+            Keys::All => true,
+            Keys::Letters => !is_arrow,
+            Keys::Arrows => is_arrow,
+        };
+
+        // Direction keys are queued up, and the snake takes at most one from the queue per step.
+        if mine {
+            self.queue.push_key(state.snakes()[snake].direction(), key);
+        }
+        mine
+    }
+
+    fn next(&mut self, _state: &GameState, _snake: usize) -> Option<Direction> {
+        self.queue.pop()
+    }
+}
+
+/// Steers a snake from a fixed list of turns, each given with the tick that it happens on.
+pub struct Scripted {
+    turns: Vec<(u32, Direction)>,
+}
+
+impl Scripted {
+    /// Creates a controller which makes the given turns. The turns must be in order of their
+    /// tick, with at most one turn on each tick.
+    #[must_use]
+    pub const fn new(turns: Vec<(u32, Direction)>) -> Self {
+        Self { turns }
+    }
+}
+
+impl Controller for Scripted {
+    fn next(&mut self, state: &GameState, _snake: usize) -> Option<Direction> {
+        let tick = state.ticks();
+        let idx = self.turns.partition_point(|&(t, _)| t < tick);
+        self.turns
+            .get(idx)
+            .filter(|&&(t, _)| t == tick)
+            .map(|&(_, dir)| dir)
+    }
+}
+
+/// Lets the [`autopilot`] steer a snake.
+pub struct Autopilot;
+
+impl Controller for Autopilot {
+    fn next(&mut self, state: &GameState, snake: usize) -> Option<Direction> {
+        autopilot::choose(state, snake)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oca_term::Key;
+
+    use super::{Autopilot, Controller, Keyboard, Scripted, inputs, keyboards};
+    use crate::{
+        game::{Direction, GameConfig, GameState},
+        level::Level,
+    };
+
+    /// Every turn made by each snake, along with the tick that it happened on.
+    type Turns = Vec<Vec<(u32, Direction)>>;
+
+    /// Plays a game through to the end with the given controllers, recording the turns they made.
+    fn play(
+        config: GameConfig,
+        controllers: &mut [Box<dyn Controller + '_>],
+    ) -> (GameState, Turns) {
+        let mut state = GameState::new(3, config, &Level::classic());
+        let mut turns = vec![Vec::new(); controllers.len()];
+        while !state.is_finished() {
+            let inputs = inputs(controllers, &state);
+            for (turns, input) in turns.iter_mut().zip(&inputs) {
+                turns.extend(input.map(|dir| (state.ticks(), dir)));
+            }
+            state.step(&inputs);
+        }
+        (state, turns)
+    }
+
+    #[test]
+    fn keyboard_keys() {
+        let config = GameConfig {
+            two_player: true,
+            ..GameConfig::default()
+        };
+        let state = GameState::new(0, config, &Level::classic());
+        let mut controllers = keyboards(config);
+
+        assert!(controllers[0].key(&state, 0, Key::Char(b'w')));
+        assert!(!controllers[1].key(&state, 1, Key::Char(b'w')));
+        assert!(!controllers[0].key(&state, 0, Key::Down));
+        assert!(controllers[1].key(&state, 1, Key::Down));
+        assert_eq!(
+            inputs(&mut controllers, &state),
+            [Some(Direction::Up), Some(Direction::Down)]
+        );
+        assert_eq!(inputs(&mut controllers, &state), [None, None]);
+
+        let mut keyboard = Keyboard::new();
+        assert!(keyboard.key(&state, 0, Key::Up));
+        assert_eq!(keyboard.next(&state, 0), Some(Direction::Up));
+    }
+
+    #[test]
+    fn scripted() {
+        // Turn up into the top wall.
+        let mut controllers: [Box<dyn Controller>; 1] =
+            [Box::new(Scripted::new(vec![(2, Direction::Up)]))];
+        let (state, turns) = play(GameConfig::default(), &mut controllers);
+        assert_eq!(turns, [[(2, Direction::Up)]]);
+        assert_eq!(state.snakes()[0].head().x, 5);
+        assert_eq!(state.snakes()[0].head().y, 0);
+    }
+
+    #[test]
+    fn autopilot_and_script() {
+        let config = GameConfig {
+            two_player: true,
+            ..GameConfig::default()
+        };
+        let mut controllers: [Box<dyn Controller>; 2] = [Box::new(Autopilot), Box::new(Autopilot)];
+        let (game, turns) = play(config, &mut controllers);
+        assert!(game.ticks() > 100);
+
+        // Making the same turns again gives exactly the same game.
+        let mut controllers = turns
+            .into_iter()
+            .map(|turns| Box::new(Scripted::new(turns)) as Box<dyn Controller>)
+            .collect::<Vec<_>>();
+        let (state, _) = play(config, &mut controllers);
+        assert_eq!(state.ticks(), game.ticks());
+        assert_eq!(state.winner(), game.winner());
+        for (a, b) in state.snakes().iter().zip(game.snakes()) {
+            assert!(a.tail().eq(b.tail()));
+            assert_eq!(a.score(), b.score());
+        }
+    }
+}
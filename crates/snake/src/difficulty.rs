@@ -0,0 +1,192 @@
+//! Difficulty presets.
+//!
+//! A [`Difficulty`] decides how fast the game starts, how quickly it speeds up as fruits are eaten
+//! (the [`SpeedCurve`]), how fast it can get, how long the snake starts, and how many fruits are
+//! on the canvas. Each difficulty has its own leaderboard in the `snake` binary, so that scores are
+//! comparable.
+
+use core::time::Duration;
+
+use crate::game::{STARTING_LENGTH, STARTING_STEP_TIME};
+
+/// How hard a game is. The player chooses this on the welcome screen.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+/// How the time between each step shrinks as fruits are eaten.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SpeedCurve {
+    /// The step time shrinks by the same amount for every fruit.
+    Linear(Duration),
+    /// The step time shrinks by `by` for every `every` fruits.
+    Stepped { every: usize, by: Duration },
+    /// The step time is multiplied by `percent`% for every fruit.
+    Exponential { percent: u32 },
+}
+
+impl SpeedCurve {
+    /// Returns the new step time after a fruit is eaten, given the current step time and the total
+    /// number of fruits that have been eaten (including this one).
+    #[must_use]
+    pub fn next(self, step_time: Duration, eaten: usize) -> Duration {
+        match self {
+            Self::Linear(by) => step_time.saturating_sub(by),
+            Self::Stepped { every, by } if eaten.is_multiple_of(every) => {
+                step_time.saturating_sub(by)
+            }
+            Self::Stepped { .. } => step_time,
+            Self::Exponential { percent } => step_time * percent / 100,
+        }
+    }
+}
+
+/// The rules chosen by a [`Difficulty`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DifficultySettings {
+    /// The time between each step at the start of the game, on a level that doesn't choose its
+    /// own speed.
+    pub step_time: Duration,
+    /// How the step time shrinks as fruits are eaten.
+    pub curve: SpeedCurve,
+    /// The step time never shrinks below this.
+    pub min_step_time: Duration,
+    /// The length that the snake grows to at the start of the game.
+    pub starting_length: usize,
+    /// The number of fruits on the canvas at once.
+    pub fruits: usize,
+}
+
+impl Difficulty {
+    /// Every difficulty, from easiest to hardest.
+    pub const ALL: [Self; 4] = [Self::Easy, Self::Normal, Self::Hard, Self::Insane];
+
+    /// Returns the rules for this difficulty.
+    #[must_use]
+    pub const fn settings(self) -> DifficultySettings {
+        match self {
+            Self::Easy => DifficultySettings {
+                step_time: Duration::from_millis(180),
+                curve: SpeedCurve::Stepped {
+                    every: 5,
+                    by: Duration::from_millis(5),
+                },
+                min_step_time: Duration::from_millis(100),
+                starting_length: 5,
+                fruits: 7,
+            },
+            // These are the rules that the game has always had, except for the minimum step time.
+            Self::Normal => DifficultySettings {
+                step_time: STARTING_STEP_TIME,
+                curve: SpeedCurve::Linear(Duration::from_micros(500)),
+                min_step_time: Duration::from_millis(50),
+                starting_length: STARTING_LENGTH,
+                fruits: 5,
+            },
+            Self::Hard => DifficultySettings {
+                step_time: Duration::from_millis(110),
+                curve: SpeedCurve::Linear(Duration::from_millis(1)),
+                min_step_time: Duration::from_millis(50),
+                starting_length: 9,
+                fruits: 4,
+            },
+            Self::Insane => DifficultySettings {
+                step_time: Duration::from_millis(80),
+                curve: SpeedCurve::Exponential { percent: 98 },
+                min_step_time: Duration::from_millis(35),
+                starting_length: 12,
+                fruits: 3,
+            },
+        }
+    }
+
+    /// Returns the name of this difficulty, as shown to the player.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Easy => "EASY",
+            Self::Normal => "NORMAL",
+            Self::Hard => "HARD",
+            Self::Insane => "INSANE",
+        }
+    }
+
+    /// Returns the next (harder) difficulty, wrapping around to the easiest.
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Easy => Self::Normal,
+            Self::Normal => Self::Hard,
+            Self::Hard => Self::Insane,
+            Self::Insane => Self::Easy,
+        }
+    }
+
+    /// Returns the previous (easier) difficulty, wrapping around to the hardest.
+    #[must_use]
+    pub const fn prev(self) -> Self {
+        match self {
+            Self::Easy => Self::Insane,
+            Self::Normal => Self::Easy,
+            Self::Hard => Self::Normal,
+            Self::Insane => Self::Hard,
+        }
+    }
+
+    /// Returns the difficulty as a byte, for storing in replays.
+    #[must_use]
+    pub const fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// The inverse of [`Self::to_byte`].
+    #[must_use]
+    pub const fn from_byte(byte: u8) -> Option<Self> {
+        if (byte as usize) < Self::ALL.len() {
+            Some(Self::ALL[byte as usize])
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{Difficulty, SpeedCurve};
+
+    #[test]
+    fn speed_curves() {
+        let ms = Duration::from_millis;
+        assert_eq!(SpeedCurve::Linear(ms(2)).next(ms(100), 1), ms(98));
+        assert_eq!(SpeedCurve::Linear(ms(2)).next(ms(1), 1), ms(0));
+
+        let stepped = SpeedCurve::Stepped {
+            every: 3,
+            by: ms(10),
+        };
+        assert_eq!(stepped.next(ms(100), 2), ms(100));
+        assert_eq!(stepped.next(ms(100), 3), ms(90));
+
+        let exponential = SpeedCurve::Exponential { percent: 90 };
+        assert_eq!(exponential.next(ms(100), 1), ms(90));
+        assert_eq!(exponential.next(ms(90), 2), ms(81));
+    }
+
+    #[test]
+    fn byte_round_trip() {
+        for difficulty in Difficulty::ALL {
+            assert_eq!(
+                Difficulty::from_byte(difficulty.to_byte()),
+                Some(difficulty)
+            );
+        }
+        assert_eq!(Difficulty::from_byte(4), None);
+    }
+}
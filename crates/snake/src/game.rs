@@ -0,0 +1,1873 @@
+//! Headless game rules for Snake.
+//!
+//! This module contains the rules of the game, completely separated from the terminal. The
+//! [`GameState`] type owns the snakes, the fruits and the bitboard, and is advanced one tick at a
+//! time with [`GameState::step`]. Each step returns a list of [`GameEvent`]s which describe what
+//! happened, so that a front-end (such as the `snake` binary) can draw them.
+//!
+//! Nothing in this module draws to the screen or sleeps, which means that the rules can be unit
+//! tested and simulated without a terminal.
+
+use core::{cmp::Ordering, time::Duration};
+use std::collections::VecDeque;
+
+use oca_io::{CircularBuffer, Rng};
+use oca_term::Key;
+
+use crate::{
+    difficulty::{Difficulty, DifficultySettings},
+    level::Level,
+};
+
+/// Defines the time between each movement of the snake on normal difficulty, unless the level
+/// chooses its own speed.
+///
+/// Over the couse of the game, this value will decrease (see [`crate::difficulty::SpeedCurve`]).
+pub const STARTING_STEP_TIME: Duration = Duration::from_millis(140);
+
+/// Defines the starting length of the snake on normal difficulty. Note that the snake does not
+/// actualy start at this length, but slowly expands out of a single point.
+pub const STARTING_LENGTH: usize = 7;
+
+/// The score reported when the player fills the entire canvas. This is also the highest score,
+/// so that scores always fit in three digits.
+pub const WIN_SCORE: usize = 999;
+
+/// The most snakes that can be on the canvas at once.
+pub const MAX_PLAYERS: usize = 2;
+
+/// Roughly one in this many normal fruits that are eaten makes a special fruit appear, as long as
+/// there isn't one on the canvas already.
+const SPECIAL_FRUIT_CHANCE: usize = 6;
+
+/// The number of points that a [`FruitKind::Golden`] fruit is worth.
+pub const GOLDEN_POINTS: usize = 5;
+
+/// How long a [`FruitKind::Golden`] fruit stays on the canvas before disappearing.
+const GOLDEN_LIFETIME: Duration = Duration::from_secs(5);
+
+/// How long the other special fruits stay on the canvas before disappearing.
+const SPECIAL_LIFETIME: Duration = Duration::from_secs(10);
+
+/// The number of cells that a [`FruitKind::Shrink`] fruit cuts off the snake's tail.
+const SHRINK_LENGTH: usize = 4;
+
+/// A [`FruitKind::SlowDown`] fruit restores the step time from this many fruits ago.
+const SLOW_DOWN_FRUITS: usize = 10;
+
+/// How long a [`FruitKind::SlowDown`] fruit slows the game down for.
+const SLOW_DOWN_TIME: Duration = Duration::from_secs(5);
+
+/// With combo scoring, a fruit eaten within this many ticks of the last one raises the snake's
+/// multiplier. If the snake doesn't eat for this many ticks, then the multiplier drops by one.
+const COMBO_TICKS: u32 = 30;
+
+/// The highest that the combo multiplier can go.
+pub const MAX_MULTIPLIER: usize = 5;
+
+/// How long a time attack game lasts.
+pub const TIME_ATTACK_LIMIT: Duration = Duration::from_mins(2);
+
+/// The number of points that a snake loses each time it crashes in time attack.
+pub const CRASH_PENALTY: usize = 5;
+
+/// In survival, how often the walls close in by another ring.
+pub const SHRINK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// In survival, the walls stop closing in once the space inside them would be narrower than this.
+const MIN_ARENA: u16 = 6;
+
+/// The number of pairs of portals on the canvas, when playing with portals.
+pub const PORTAL_PAIRS: usize = 2;
+
+/// What the goal of a game is.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum GameMode {
+    /// Score as much as possible before dying.
+    #[default]
+    Classic,
+    /// Score as much as possible within [`TIME_ATTACK_LIMIT`]. A snake that crashes loses points
+    /// and starts again instead of dying (see [`GameEvent::Crashed`]).
+    TimeAttack,
+    /// Survive while the walls close in by a ring every [`SHRINK_INTERVAL`] (see
+    /// [`GameEvent::NewWall`]).
+    Survival,
+    /// Score as much as possible, on a canvas with [`PORTAL_PAIRS`] pairs of [`Portal`]s.
+    Portals,
+}
+
+impl GameMode {
+    /// Every mode, in the order of their replay format values.
+    pub const ALL: [Self; 4] = [
+        Self::Classic,
+        Self::TimeAttack,
+        Self::Survival,
+        Self::Portals,
+    ];
+
+    /// Returns the mode's value in the replay format.
+    #[must_use]
+    pub const fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the mode with the given value in the replay format, if there is one.
+    #[must_use]
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(byte)).copied()
+    }
+}
+
+/// Options that change the rules of the game.
+///
+/// These are chosen before a game starts and never change during it.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct GameConfig {
+    /// If `true`, then the snake's head wraps around to the opposite side of the canvas instead
+    /// of hitting the wall.
+    pub wrap: bool,
+    /// If `true`, then a second snake plays against the first, starting from the opposite side
+    /// of the level (see [`Level::second_start`]).
+    pub two_player: bool,
+    /// How hard the game is.
+    pub difficulty: Difficulty,
+    /// If `true`, then eating fruits in quick succession builds up a multiplier for their points
+    /// (see [`GameEvent::Multiplier`]).
+    pub combo: bool,
+    /// What the goal of the game is. Modes other than [`GameMode::Classic`] are only played by
+    /// one player.
+    pub mode: GameMode,
+}
+
+impl GameConfig {
+    /// Returns the number of snakes on the canvas.
+    #[must_use]
+    pub const fn players(self) -> usize {
+        if self.two_player { 2 } else { 1 }
+    }
+}
+
+/// Something that happened during a single [`GameState::step`]. Snakes are identified by their
+/// index into [`GameState::snakes`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameEvent {
+    /// The snake's head moved from `old` to `head`.
+    Moved {
+        snake: usize,
+        old: Coord,
+        head: Coord,
+    },
+    /// The end of a tail left this coordinate, which is now empty.
+    Trimmed(Coord),
+    /// The snake ate a fruit of the given kind.
+    AteFruit { snake: usize, kind: FruitKind },
+    /// A new fruit was placed on the canvas.
+    NewFruit(Fruit),
+    /// The snake's combo multiplier changed.
+    Multiplier { snake: usize, multiplier: usize },
+    /// A special fruit wasn't eaten in time, and disappeared from this coordinate.
+    FruitExpired(Coord),
+    /// The snake hit the edge of the canvas, a wall, a tail or the other snake's head. The game
+    /// is over.
+    Died { snake: usize, cause: DeathCause },
+    /// In time attack, the snake hit something and lost [`CRASH_PENALTY`] points. Its whole tail
+    /// was trimmed, and it starts again with its head at `head`.
+    Crashed {
+        snake: usize,
+        cause: DeathCause,
+        head: Coord,
+    },
+    /// In survival, the walls closed in, and this coordinate is now a wall.
+    NewWall(Coord),
+    /// There is no space left for another fruit: the canvas has been filled. The game is over.
+    Won,
+    /// In time attack, the time ran out. The game is over.
+    TimeUp,
+}
+
+/// What a snake hit when it died.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DeathCause {
+    /// The edge of the canvas, or one of the level's walls.
+    Wall,
+    /// Its own tail.
+    Tail,
+    /// The other snake, either its tail or head-to-head.
+    Snake,
+}
+
+/// The kinds of fruit. Most fruits are [`FruitKind::Normal`], but every so often a rarer, special
+/// fruit appears alongside them. Special fruits disappear if they aren't eaten quickly.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FruitKind {
+    /// Worth one point, and grows the snake by one cell.
+    Normal,
+    /// Worth [`GOLDEN_POINTS`] points, but only stays on the canvas for a few seconds.
+    Golden,
+    /// Worth one point, and cuts the end off the snake's tail instead of growing it.
+    Shrink,
+    /// Worth one point, and briefly slows the game down to an earlier speed.
+    SlowDown,
+}
+
+impl FruitKind {
+    /// The kinds of special fruit, which are chosen between at random.
+    const SPECIAL: [Self; 3] = [Self::Golden, Self::Shrink, Self::SlowDown];
+
+    /// Returns the number of points that eating this fruit is worth.
+    #[must_use]
+    pub const fn points(self) -> usize {
+        match self {
+            Self::Golden => GOLDEN_POINTS,
+            Self::Normal | Self::Shrink | Self::SlowDown => 1,
+        }
+    }
+
+    /// Returns how long this fruit stays on the canvas, or [`None`] if it stays until eaten.
+    const fn lifetime(self) -> Option<Duration> {
+        match self {
+            Self::Normal => None,
+            Self::Golden => Some(GOLDEN_LIFETIME),
+            Self::Shrink | Self::SlowDown => Some(SPECIAL_LIFETIME),
+        }
+    }
+}
+
+/// A fruit on the canvas.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Fruit {
+    /// Where the fruit is.
+    pub coord: Coord,
+    /// What eating the fruit does.
+    pub kind: FruitKind,
+    /// The tick on which the fruit disappears, if it isn't eaten first.
+    expires: Option<u32>,
+}
+
+/// A single snake on the canvas.
+pub struct Snake {
+    /// The snake's head position.
+    head: Coord,
+    /// The direction that the snake is currently moving in.
+    direction: Direction,
+    /// The tail, with the oldest coordinate at the front and the head at the back. We add to it
+    /// and trim it to keep it no longer than `len`.
+    tail: VecDeque<Coord>,
+    /// The length that the snake is growing towards.
+    len: usize,
+    /// The number of fruits that the snake has eaten.
+    eaten: usize,
+    /// The number of points that the snake has scored, which depends on the fruits it ate.
+    score: usize,
+    /// The combo multiplier, which the points of each fruit are multiplied by. With combo scoring,
+    /// each fruit eaten soon after the last one raises the multiplier (up to [`MAX_MULTIPLIER`]),
+    /// and it drops again while the snake goes without eating. Without combo scoring, it is
+    /// always 1.
+    multiplier: usize,
+    /// The number of ticks until the combo multiplier drops, or zero if it can't drop.
+    combo_ticks: u32,
+    /// Whether the snake is still alive.
+    alive: bool,
+}
+
+impl Snake {
+    /// Creates a new snake, which starts out as a single point (its head) and grows to `len`. The
+    /// tail has room to fill the whole canvas.
+    fn new(head: Coord, direction: Direction, len: usize, size: CanvasSize) -> Self {
+        let mut tail = VecDeque::with_capacity(size.cells());
+        tail.push_back(head);
+        Self {
+            head,
+            direction,
+            tail,
+            len,
+            eaten: 0,
+            score: 0,
+            multiplier: 1,
+            combo_ticks: 0,
+            alive: true,
+        }
+    }
+
+    /// Returns the current position of the snake's head.
+    #[must_use]
+    pub const fn head(&self) -> Coord {
+        self.head
+    }
+
+    /// Returns the direction the snake is currently moving in.
+    #[must_use]
+    pub const fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns an iterator over the snake, from the end of the tail up to (and including) the
+    /// head.
+    #[must_use]
+    pub fn tail(&self) -> impl DoubleEndedIterator<Item = Coord> + '_ {
+        self.tail.iter().copied()
+    }
+
+    /// Returns the snake's score: the points for each fruit it has eaten (see
+    /// [`FruitKind::points`]).
+    #[must_use]
+    pub const fn score(&self) -> usize {
+        self.score
+    }
+
+    /// Returns the number of fruits that the snake has eaten.
+    #[must_use]
+    pub const fn eaten(&self) -> usize {
+        self.eaten
+    }
+
+    /// Returns `false` once the snake has died.
+    #[must_use]
+    pub const fn is_alive(&self) -> bool {
+        self.alive
+    }
+}
+
+/// The complete state of a single game of Snake.
+pub struct GameState {
+    /// The rules that this game is being played with.
+    config: GameConfig,
+    /// The size of the canvas, which is chosen by the level.
+    size: CanvasSize,
+    /// The rules chosen by the game's difficulty.
+    settings: DifficultySettings,
+    /// The random number generator used to place fruits.
+    rng: Rng,
+    /// The snakes on the canvas: one, or two in a two-player game.
+    snakes: Vec<Snake>,
+    /// Cells that are occupied, either by a snake, a fruit or a wall.
+    bitboard: Bitboard,
+    /// The level's walls, which never move, followed by any rings of wall that have closed in
+    /// during survival.
+    walls: Vec<Coord>,
+    /// In survival, the number of rings of wall that have closed in.
+    rings: u16,
+    /// The pairs of portals on the canvas, if playing with portals.
+    portals: Vec<Portal>,
+    /// Where the (first) snake starts, and the direction it starts in. In time attack, a snake
+    /// that crashes starts again from here.
+    start: (Coord, Direction),
+    /// The fruits currently on the canvas: the normal fruits, followed by at most one special
+    /// fruit.
+    fruits: Vec<Fruit>,
+    /// The current time between each step of the game, ignoring any slow-down.
+    step_time: Duration,
+    /// The step time before each fruit was eaten, oldest first.
+    step_times: Vec<Duration>,
+    /// If a [`FruitKind::SlowDown`] fruit has been eaten, then the step time that it restored, and
+    /// the tick on which the slow-down ends.
+    slow_down: Option<(Duration, u32)>,
+    /// The number of steps so far.
+    ticks: u32,
+    /// How long the game has been played for: the sum of the step time of every step so far.
+    elapsed: Duration,
+    /// Whether the game has finished (a snake has died, or the canvas has been filled).
+    finished: bool,
+    /// Whether the canvas has been filled.
+    won: bool,
+}
+
+impl GameState {
+    /// Creates a new game on the given [`Level`], with the snakes, fruits and walls where the
+    /// level puts them. The canvas is the level's size (see [`Level::fit`]).
+    ///
+    /// The game's difficulty chooses how many fruits there are. If the level doesn't have enough
+    /// fruits, then the rest are placed randomly. The difficulty also scales the level's speed,
+    /// which is given for normal difficulty.
+    ///
+    /// New fruits are placed using a random number generator seeded from `seed`; see
+    /// [`Rng::random_seed`] for generating a seed.
+    #[must_use]
+    pub fn new(seed: u64, config: GameConfig, level: &Level) -> Self {
+        let settings = config.difficulty.settings();
+        let size = level.size;
+
+        let mut snakes = vec![Snake::new(
+            level.start,
+            level.direction,
+            settings.starting_length,
+            size,
+        )];
+        if config.two_player {
+            let (start, direction) = level.second_start();
+            snakes.push(Snake::new(start, direction, settings.starting_length, size));
+        }
+
+        let step_time = level.step_time * settings.step_time.as_micros() as u32
+            / STARTING_STEP_TIME.as_micros() as u32;
+
+        let mut state = Self {
+            config,
+            size,
+            settings,
+            rng: Rng::new(seed),
+            snakes,
+            bitboard: Bitboard::new(size),
+            walls: level.walls.clone(),
+            rings: 0,
+            portals: Vec::new(),
+            start: (level.start, level.direction),
+            fruits: level
+                .fruits
+                .iter()
+                .take(settings.fruits)
+                .map(|&coord| Fruit::normal(coord))
+                .collect(),
+            step_time,
+            step_times: Vec::new(),
+            slow_down: None,
+            ticks: 0,
+            elapsed: Duration::ZERO,
+            finished: false,
+            won: false,
+        };
+
+        // Walls and fruits are both permanently occupied as far as new fruits are concerned.
+        for &coord in &level.walls {
+            state.bitboard.set(coord, true);
+        }
+        for fruit in &state.fruits {
+            state.bitboard.set(fruit.coord, true);
+        }
+        for snake in &state.snakes {
+            state.bitboard.set(snake.head, true);
+        }
+
+        // Portals stay on the bitboard too, so they are placed before the random fruits.
+        if config.mode == GameMode::Portals {
+            for _ in 0..PORTAL_PAIRS {
+                let Some(a) = state.gen_portal() else {
+                    break;
+                };
+                let Some(b) = state.gen_portal() else {
+                    state.bitboard.set(a, false);
+                    break;
+                };
+                state.portals.push(Portal(a, b));
+            }
+        }
+
+        // Add random fruits until there are enough.
+        while state.fruits.len() < settings.fruits
+            && let Some(coord) = state.gen_fruit()
+        {
+            state.fruits.push(Fruit::normal(coord));
+        }
+
+        state
+    }
+
+    /// Advances the game by a single tick.
+    ///
+    /// `inputs` holds a direction for each snake (missing entries are treated as [`None`]). If a
+    /// snake's input is [`Some`], then it turns to face that direction before moving. Callers
+    /// are expected to have already validated the direction with [`Direction::change_from_key`].
+    ///
+    /// Once [`GameEvent::Died`], [`GameEvent::Won`] or [`GameEvent::TimeUp`] has been returned,
+    /// further calls do nothing.
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+
+        self.ticks += 1;
+        self.elapsed += self.step_time();
+
+        self.expire(&mut events);
+        if self.config.combo {
+            self.decay_combos(&mut events);
+        }
+
+        for (snake, &input) in self.snakes.iter_mut().zip(inputs) {
+            if let Some(dir) = input {
+                snake.direction = dir;
+            }
+        }
+
+        // Work out where each snake's head will be, checking to see if it has hit the edge of
+        // the canvas (unless the walls wrap around). A head that enters a portal comes out of the
+        // other end, so the snake's tail is split between the two.
+        let heads: Vec<_> = self
+            .snakes
+            .iter()
+            .map(|snake| {
+                snake
+                    .head
+                    .neighbour(snake.direction, self.size, self.config.wrap, &self.portals)
+            })
+            .collect();
+
+        // If a snake has encountered *something* other than a fruit (a tail, or a wall), then it
+        // dies.
+        let mut dies: Vec<_> = heads
+            .iter()
+            .map(|&head| head.is_none_or(|h| self.bitboard.get(h) && self.fruit_at(h).is_none()))
+            .collect();
+
+        // If the snakes meet head-to-head (either on the same cell, or by swapping places), then
+        // the longer snake wins. If they are the same length, then they both die.
+        if let [Some(a), Some(b)] = heads[..] {
+            let swapped = a == self.snakes[1].head && b == self.snakes[0].head;
+            if a == b || swapped {
+                let order = self.snakes[0].len.cmp(&self.snakes[1].len);
+                let lost = [order != Ordering::Greater, order != Ordering::Less];
+                for (dies, lost) in dies.iter_mut().zip(lost) {
+                    // When swapping places, each head "hits" the other, which isn't a real death.
+                    *dies = lost || (*dies && !swapped);
+                }
+            }
+        }
+
+        // In time attack, snakes don't die: they crash and start again, and don't move this step.
+        let mut heads = heads;
+        if self.config.mode == GameMode::TimeAttack {
+            for (idx, head) in heads.iter_mut().enumerate() {
+                if dies[idx] {
+                    self.crash(idx, *head, &mut events);
+                    *head = None;
+                }
+            }
+        }
+        // Note that on death, we don't update any heads, otherwise the death animation mucks up.
+        else if dies.contains(&true) {
+            for (idx, &head) in heads.iter().enumerate() {
+                if dies[idx] {
+                    let cause = self.death_cause(idx, head);
+                    self.snakes[idx].alive = false;
+                    events.push(GameEvent::Died { snake: idx, cause });
+                }
+            }
+            self.finished = true;
+            return events;
+        }
+
+        for (idx, head) in heads.into_iter().enumerate() {
+            let Some(head) = head else {
+                // The snake crashed, and has already started again.
+                continue;
+            };
+
+            // If the snake has encountered something, then we know it is a fruit.
+            if let Some(fruit) = self.fruit_at(head) {
+                self.eat(idx, fruit, &mut events);
+                if self.won {
+                    return events;
+                }
+            }
+
+            // Move the head, putting it into the tail and marking it as occupied on the bitboard.
+            let snake = &mut self.snakes[idx];
+            let old = snake.head;
+            snake.head = head;
+            snake.tail.push_back(head);
+            self.bitboard.set(head, true);
+
+            // If the tail is longer than the snake's length, trim it. It is usually only one cell
+            // too long, unless the snake just ate a shrink fruit.
+            while snake.tail.len() > snake.len {
+                let coord = snake.tail.pop_front().unwrap();
+                self.bitboard.set(coord, false);
+                events.push(GameEvent::Trimmed(coord));
+            }
+
+            events.push(GameEvent::Moved {
+                snake: idx,
+                old,
+                head,
+            });
+        }
+
+        if self.config.mode == GameMode::TimeAttack && self.elapsed >= TIME_ATTACK_LIMIT {
+            self.finished = true;
+            events.push(GameEvent::TimeUp);
+        }
+        if self.config.mode == GameMode::Survival
+            && self.elapsed >= SHRINK_INTERVAL * (u32::from(self.rings) + 1)
+        {
+            self.shrink(&mut events);
+        }
+
+        events
+    }
+
+    /// In survival, closes the walls in by another ring, unless the space inside would be too
+    /// small. A snake that the new ring lands on dies, and fruits that it lands on are moved.
+    fn shrink(&mut self, events: &mut Vec<GameEvent>) {
+        let ring = self.rings;
+        let (w, h) = (self.size.w, self.size.h);
+        if w.min(h) < 2 * (ring + 1) + MIN_ARENA {
+            return;
+        }
+        self.rings += 1;
+
+        let on_ring = |coord: Coord| {
+            let (x, y) = (coord.x, coord.y);
+            (ring..w - ring).contains(&x)
+                && (ring..h - ring).contains(&y)
+                && (x == ring || x == w - 1 - ring || y == ring || y == h - 1 - ring)
+        };
+        for coord in self.size.coords().filter(|&coord| on_ring(coord)) {
+            if !self.walls.contains(&coord) {
+                self.walls.push(coord);
+                self.bitboard.set(coord, true);
+                events.push(GameEvent::NewWall(coord));
+            }
+        }
+
+        // The walls are on the bitboard now, so the fruits won't be moved onto the ring.
+        let mut idx = 0;
+        while idx < self.fruits.len() {
+            if on_ring(self.fruits[idx].coord) {
+                if let Some(coord) = self.gen_fruit() {
+                    self.fruits[idx].coord = coord;
+                    events.push(GameEvent::NewFruit(self.fruits[idx]));
+                } else {
+                    self.fruits.remove(idx);
+                    continue;
+                }
+            }
+            idx += 1;
+        }
+
+        for (idx, snake) in self.snakes.iter_mut().enumerate() {
+            if snake.tail.iter().any(|&coord| on_ring(coord)) {
+                snake.alive = false;
+                self.finished = true;
+                events.push(GameEvent::Died {
+                    snake: idx,
+                    cause: DeathCause::Wall,
+                });
+            }
+        }
+    }
+
+    /// In time attack, takes [`CRASH_PENALTY`] points off a snake that crashed (given where its
+    /// head would have moved to), and starts it again from the level's starting position.
+    fn crash(&mut self, snake: usize, head: Option<Coord>, events: &mut Vec<GameEvent>) {
+        let cause = self.death_cause(snake, head);
+        let (start, direction) = self.start;
+
+        let crashed = &mut self.snakes[snake];
+        for coord in crashed.tail.drain(..) {
+            self.bitboard.set(coord, false);
+            events.push(GameEvent::Trimmed(coord));
+        }
+        crashed.head = start;
+        crashed.direction = direction;
+        crashed.tail.push_back(start);
+        crashed.len = self.settings.starting_length;
+        crashed.score = crashed.score.saturating_sub(CRASH_PENALTY);
+        crashed.combo_ticks = 0;
+        if crashed.multiplier > 1 {
+            crashed.multiplier = 1;
+            events.push(GameEvent::Multiplier {
+                snake,
+                multiplier: 1,
+            });
+        }
+
+        // If a fruit has been placed where the snake starts, then move it out of the way.
+        if let Some(fruit) = self.fruit_at(start) {
+            if let Some(coord) = self.gen_fruit() {
+                self.fruits[fruit].coord = coord;
+                events.push(GameEvent::NewFruit(self.fruits[fruit]));
+            } else {
+                self.fruits.remove(fruit);
+            }
+        }
+        self.bitboard.set(start, true);
+
+        events.push(GameEvent::Crashed {
+            snake,
+            cause,
+            head: start,
+        });
+    }
+
+    /// Creates a fruit at a random position on the canvas, accounting for other fruits and the
+    /// snake.
+    ///
+    /// The naive approach to generating fruits is simple:
+    /// 1. Choose a random location.
+    /// 2. If it is not free, then repeat 1.
+    /// 3. Place the fruit on the canvas.
+    ///
+    /// This approach has a significant issue. When there are few free squares left (that is, when
+    /// the snake is very long), a possibly infinite number of random numbers might be generated.
+    /// In other words, there is no fixed upper time bound for this algorithm.
+    ///
+    /// Instead, a better algorithm is used that is O(n) on the size of the canvas:
+    /// 1. Calculate the number of free squares.
+    /// 2. Generate a random number between 0 and the number of free squares.
+    /// 3. Map the generated index onto the canvas (we iterate over the whole canvas, and only
+    ///    increment on free squares).
+    /// 4. Place the fruit on the canvas.
+    ///
+    /// This function returns [`None`] if no valid location was found, and thus, the player has
+    /// beaten the game (they have filled the screen).
+    fn gen_fruit(&mut self) -> Option<Coord> {
+        // Calculate how many free squares there are. If there are none, then the player has
+        // entirely filled the screen; they have won.
+        let free = self.size.cells() - self.bitboard.count();
+        if free == 0 {
+            return None;
+        }
+
+        // Choose our target square based on the number of free squares.
+        let target_idx = self.rng.below(free);
+
+        // For each xy point on the canvas, only counting the free squares, find our target.
+        let coord = self
+            .size
+            .coords()
+            .filter(|&coord| !self.bitboard.get(coord))
+            .nth(target_idx)?;
+
+        // Mark our new fruit's location on the bitboard.
+        self.bitboard.set(coord, true);
+        Some(coord)
+    }
+
+    /// Removes any special fruits that weren't eaten in time, and ends the slow-down once it has
+    /// run out.
+    fn expire(&mut self, events: &mut Vec<GameEvent>) {
+        let ticks = self.ticks;
+        self.fruits.retain(|fruit| {
+            if fruit.expires.is_some_and(|expires| expires <= ticks) {
+                self.bitboard.set(fruit.coord, false);
+                events.push(GameEvent::FruitExpired(fruit.coord));
+                false
+            } else {
+                true
+            }
+        });
+        if self.slow_down.is_some_and(|(_, ends)| ends <= ticks) {
+            self.slow_down = None;
+        }
+    }
+
+    /// Counts down each snake's combo, dropping its multiplier by one each time the snake goes
+    /// [`COMBO_TICKS`] without eating.
+    fn decay_combos(&mut self, events: &mut Vec<GameEvent>) {
+        for (idx, snake) in self.snakes.iter_mut().enumerate() {
+            if snake.combo_ticks == 0 {
+                continue;
+            }
+
+            snake.combo_ticks -= 1;
+            if snake.combo_ticks == 0 && snake.multiplier > 1 {
+                snake.multiplier -= 1;
+                snake.combo_ticks = COMBO_TICKS;
+                events.push(GameEvent::Multiplier {
+                    snake: idx,
+                    multiplier: snake.multiplier,
+                });
+            }
+        }
+    }
+
+    /// Has a snake eat the fruit at the given index into [`Self::fruits`], growing (or shrinking)
+    /// it, speeding up the game and replacing the fruit. If there is nowhere left to put the new
+    /// fruit, then the game is won.
+    fn eat(&mut self, snake: usize, fruit: usize, events: &mut Vec<GameEvent>) {
+        let kind = self.fruits[fruit].kind;
+        let combo = self.config.combo;
+        let hungry = &mut self.snakes[snake];
+        hungry.eaten += 1;
+
+        // Eating soon after the last fruit raises the combo multiplier, which applies to this
+        // fruit too.
+        if combo {
+            if hungry.combo_ticks > 0 && hungry.multiplier < MAX_MULTIPLIER {
+                hungry.multiplier += 1;
+                events.push(GameEvent::Multiplier {
+                    snake,
+                    multiplier: hungry.multiplier,
+                });
+            }
+            hungry.combo_ticks = COMBO_TICKS;
+        }
+        hungry.score = (hungry.score + kind.points() * hungry.multiplier).min(WIN_SCORE);
+        if kind == FruitKind::Shrink {
+            // Never shrink away the head.
+            hungry.len = hungry.len.saturating_sub(SHRINK_LENGTH).max(1);
+        } else {
+            hungry.len += 1;
+        }
+        events.push(GameEvent::AteFruit { snake, kind });
+
+        // Speed the game up a little, following the difficulty's speed curve (but never slowing
+        // down, even if the level started faster than the minimum).
+        self.step_times.push(self.step_time);
+        let eaten = self.snakes.iter().map(|s| s.eaten).sum();
+        self.step_time = self
+            .settings
+            .curve
+            .next(self.step_time, eaten)
+            .max(self.settings.min_step_time)
+            .min(self.step_time);
+
+        // A slow-down fruit goes back to the speed from a few fruits ago, for a while.
+        if kind == FruitKind::SlowDown {
+            let earlier = self.step_times.len().saturating_sub(SLOW_DOWN_FRUITS);
+            let step_time = self.step_times[earlier];
+            let ends = self.ticks + ticks_in(SLOW_DOWN_TIME, step_time);
+            self.slow_down = Some((step_time, ends));
+        }
+
+        // Special fruits aren't replaced, they just vanish. Note that we needn't remove fruit from
+        // the bitboard because we ate it and will "digest" it (the normal snake code will remove
+        // it).
+        if kind != FruitKind::Normal {
+            self.fruits.remove(fruit);
+        } else if let Some(coord) = self.gen_fruit() {
+            // Generate another fruit to replace that one we just ate, and perhaps a special fruit
+            // too.
+            self.fruits[fruit] = Fruit::normal(coord);
+            events.push(GameEvent::NewFruit(self.fruits[fruit]));
+            if let Some(special) = self.gen_special_fruit() {
+                events.push(GameEvent::NewFruit(special));
+            }
+        } else {
+            // If we could not find a location for the fruit, then the canvas is full.
+            self.fruits.swap_remove(fruit);
+            self.finished = true;
+            self.won = true;
+            events.push(GameEvent::Won);
+        }
+    }
+
+    /// Occasionally places a special fruit on the canvas, as long as there isn't one already.
+    /// The kind of fruit is chosen at random, and it is placed in the same way as any other fruit
+    /// (see [`Self::gen_fruit`]).
+    fn gen_special_fruit(&mut self) -> Option<Fruit> {
+        if self.fruits.iter().any(|f| f.kind != FruitKind::Normal)
+            || self.rng.below(SPECIAL_FRUIT_CHANCE) != 0
+        {
+            return None;
+        }
+
+        let kind = FruitKind::SPECIAL[self.rng.below(FruitKind::SPECIAL.len())];
+        let fruit = Fruit {
+            coord: self.gen_fruit()?,
+            kind,
+            expires: kind
+                .lifetime()
+                .map(|lifetime| self.ticks + ticks_in(lifetime, self.step_time())),
+        };
+        self.fruits.push(fruit);
+        Some(fruit)
+    }
+
+    /// Chooses a random cell for one end of a portal, and marks it as occupied on the bitboard.
+    ///
+    /// Portals are kept off the edge of the canvas and the row that the snake starts in, and only
+    /// go where all four neighbouring cells are empty (so never next to another portal). This way,
+    /// there is always somewhere to come out of a portal.
+    fn gen_portal(&mut self) -> Option<Coord> {
+        let start = self.start.0;
+        let free = |coord: Coord| !self.bitboard.get(coord);
+        let cells: Vec<_> = self
+            .size
+            .coords()
+            .filter(|&coord| {
+                coord.y != start.y
+                    && free(coord)
+                    && [
+                        Direction::Up,
+                        Direction::Down,
+                        Direction::Left,
+                        Direction::Right,
+                    ]
+                    .into_iter()
+                    .all(|dir| coord.step(dir, self.size).is_some_and(free))
+            })
+            .collect();
+        if cells.is_empty() {
+            return None;
+        }
+
+        let coord = cells[self.rng.below(cells.len())];
+        self.bitboard.set(coord, true);
+        Some(coord)
+    }
+
+    /// Returns the index of the fruit at the given coordinate, if there is one.
+    fn fruit_at(&self, coord: Coord) -> Option<usize> {
+        self.fruits.iter().position(|fruit| fruit.coord == coord)
+    }
+
+    /// Works out what a dying snake hit, given where its head would have moved to ([`None`] if
+    /// that is off the edge of the canvas).
+    fn death_cause(&self, snake: usize, head: Option<Coord>) -> DeathCause {
+        match head {
+            None => DeathCause::Wall,
+            Some(head) if self.walls.contains(&head) => DeathCause::Wall,
+            Some(head) if self.snakes[snake].tail().any(|coord| coord == head) => DeathCause::Tail,
+            // Otherwise, it hit the other snake (possibly head-to-head, on an empty cell).
+            Some(_) => DeathCause::Snake,
+        }
+    }
+
+    /// Returns the rules that this game is being played with.
+    #[must_use]
+    pub const fn config(&self) -> GameConfig {
+        self.config
+    }
+
+    /// Returns the size of the canvas.
+    #[must_use]
+    pub const fn size(&self) -> CanvasSize {
+        self.size
+    }
+
+    /// Returns the snakes on the canvas. The first snake is always the (first) player's.
+    #[must_use]
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    /// Returns the fruits currently on the canvas.
+    #[must_use]
+    pub fn fruits(&self) -> &[Fruit] {
+        &self.fruits
+    }
+
+    /// Returns the positions of the walls: the level's walls, and in survival, any that have
+    /// closed in since.
+    #[must_use]
+    pub fn walls(&self) -> &[Coord] {
+        &self.walls
+    }
+
+    /// Returns the pairs of portals on the canvas.
+    #[must_use]
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    /// Returns the current time between each step of the game. This is slower than usual for a
+    /// while after a [`FruitKind::SlowDown`] fruit is eaten.
+    #[must_use]
+    pub fn step_time(&self) -> Duration {
+        match self.slow_down {
+            Some((step_time, _)) => step_time.max(self.step_time),
+            None => self.step_time,
+        }
+    }
+
+    /// Returns the number of times that the game has been stepped.
+    #[must_use]
+    pub const fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Returns the first snake's score (see [`Snake::score`]). If the canvas has been filled in a
+    /// single-player game, this is [`WIN_SCORE`].
+    #[must_use]
+    pub fn score(&self) -> usize {
+        if self.won && !self.config.two_player {
+            WIN_SCORE
+        } else {
+            self.snakes[0].score()
+        }
+    }
+
+    /// Returns `true` once a snake has died, the canvas has been filled, or the time has run out.
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the index of the snake that won a finished two-player game, or [`None`] if the
+    /// game was a draw (or hasn't finished yet).
+    ///
+    /// The last snake alive wins. If the canvas was filled instead, then the higher score wins.
+    #[must_use]
+    pub fn winner(&self) -> Option<usize> {
+        if !self.finished {
+            return None;
+        }
+
+        let mut alive = self.snakes.iter().enumerate().filter(|(_, s)| s.alive);
+        match (alive.next(), alive.next()) {
+            (Some((idx, _)), None) => Some(idx),
+            (Some((a, snake_a)), Some((b, snake_b))) => match snake_a.score.cmp(&snake_b.score) {
+                Ordering::Greater => Some(a),
+                Ordering::Less => Some(b),
+                Ordering::Equal => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Fruit {
+    /// Creates a normal fruit, which stays on the canvas until it is eaten.
+    const fn normal(coord: Coord) -> Self {
+        Self {
+            coord,
+            kind: FruitKind::Normal,
+            expires: None,
+        }
+    }
+}
+
+/// Returns the number of ticks (at least one) that make up `time`, at the given step time.
+fn ticks_in(time: Duration, step_time: Duration) -> u32 {
+    (time.as_micros() / step_time.as_micros()).clamp(1, u32::MAX.into()) as u32
+}
+
+/// A set of occupied cells on the canvas, used to determine valid locations for placing fruits.
+struct Bitboard {
+    bits: Vec<u64>,
+    size: CanvasSize,
+}
+
+impl Bitboard {
+    /// Creates an empty bitboard for a canvas of the given size. We take the number of game
+    /// cells, divided by size of each value (64 bits), rounding up (so the last u64 may only be
+    /// partly filled).
+    fn new(size: CanvasSize) -> Self {
+        Self {
+            bits: vec![0; size.cells().div_ceil(64)],
+            size,
+        }
+    }
+
+    /// Mark a coordinate on the bitboard as either occupied or unoccupied.
+    fn set(&mut self, coord: Coord, value: bool) {
+        // Turn the 2d coordinate into a flat index.
+        let idx = coord.as_idx(self.size);
+        // Use magic bitwise operators to set/unset.
+        if value {
+            self.bits[idx / 64] |= 0b1 << (idx % 64);
+        } else {
+            self.bits[idx / 64] &= !(0b1 << (idx % 64));
+        }
+    }
+
+    /// Check whether a coordinate on the bitboard is occupied or unoccupied.
+    fn get(&self, coord: Coord) -> bool {
+        // Turn the 2d coordinate into a flat index.
+        let idx = coord.as_idx(self.size);
+        // Use magic bitwise operators to check if the bit is marked as occupied.
+        self.bits[idx / 64] & (0b1 << (idx % 64)) != 0
+    }
+
+    /// Count the number of occupied cells.
+    fn count(&self) -> usize {
+        self.bits.iter().map(|x| x.count_ones()).sum::<u32>() as usize
+    }
+}
+
+/// The size of the canvas, in game cells. Note that each game cell is two terminal cells wide.
+///
+/// The canvas is as big as the terminal allows, between [`CanvasSize::MIN`] and
+/// [`CanvasSize::MAX`]. Levels are stretched to fit (see [`Level::fit`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CanvasSize {
+    pub w: u16,
+    pub h: u16,
+}
+
+impl CanvasSize {
+    /// The smallest canvas, which is also the size of every level file.
+    pub const MIN: Self = Self { w: 28, h: 19 };
+
+    /// The largest canvas. Any bigger, and a game takes far too long to fill the canvas.
+    pub const MAX: Self = Self { w: 48, h: 32 };
+
+    /// Limits a size to between [`Self::MIN`] and [`Self::MAX`], returning [`None`] if it is
+    /// smaller than the minimum.
+    #[must_use]
+    pub const fn clamp(self) -> Option<Self> {
+        if self.w < Self::MIN.w || self.h < Self::MIN.h {
+            return None;
+        }
+        Some(Self {
+            w: if self.w < Self::MAX.w {
+                self.w
+            } else {
+                Self::MAX.w
+            },
+            h: if self.h < Self::MAX.h {
+                self.h
+            } else {
+                Self::MAX.h
+            },
+        })
+    }
+
+    /// Returns the total number of game cells on the canvas.
+    #[must_use]
+    pub const fn cells(self) -> usize {
+        self.w as usize * self.h as usize
+    }
+
+    /// Returns every cell of the canvas, in reading order.
+    pub fn coords(self) -> impl Iterator<Item = Coord> {
+        (0..self.h).flat_map(move |y| (0..self.w).map(move |x| Coord { x, y }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Coord {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Coord {
+    /// Returns the index of this coordinate on a canvas of the given size, in reading order.
+    #[must_use]
+    pub const fn as_idx(self, size: CanvasSize) -> usize {
+        self.y as usize * size.w as usize + self.x as usize
+    }
+
+    /// Returns the neighbouring coordinate in the given direction, or [`None`] if that would be
+    /// outside a canvas of the given size.
+    #[must_use]
+    pub const fn step(self, direction: Direction, size: CanvasSize) -> Option<Self> {
+        let Self { x, y } = self;
+        Some(match direction {
+            Direction::Up if y > 0 => Self { x, y: y - 1 },
+            Direction::Down if y < size.h - 1 => Self { x, y: y + 1 },
+            Direction::Right if x < size.w - 1 => Self { x: x + 1, y },
+            Direction::Left if x > 0 => Self { x: x - 1, y },
+            _ => return None,
+        })
+    }
+
+    /// Returns the neighbouring coordinate in the given direction, wrapping around to the
+    /// opposite side of a canvas of the given size at the edges.
+    #[must_use]
+    pub const fn step_wrapping(self, direction: Direction, size: CanvasSize) -> Self {
+        let Self { x, y } = self;
+        let CanvasSize { w, h } = size;
+        match direction {
+            Direction::Up => Self {
+                x,
+                y: (y + h - 1) % h,
+            },
+            Direction::Down => Self { x, y: (y + 1) % h },
+            Direction::Right => Self { x: (x + 1) % w, y },
+            Direction::Left => Self {
+                x: (x + w - 1) % w,
+                y,
+            },
+        }
+    }
+
+    /// Returns the neighbouring coordinate in the given direction, following the rules of a game
+    /// on a canvas of the given size: wrapping around at the edges if `wrap` is set, and coming
+    /// out of the far side of a portal's partner when entering one of `portals`. Returns [`None`]
+    /// if that would be outside the canvas.
+    #[must_use]
+    pub fn neighbour(
+        self,
+        direction: Direction,
+        size: CanvasSize,
+        wrap: bool,
+        portals: &[Portal],
+    ) -> Option<Self> {
+        let step = |coord: Self| {
+            if wrap {
+                Some(coord.step_wrapping(direction, size))
+            } else {
+                coord.step(direction, size)
+            }
+        };
+        let next = step(self)?;
+        portals
+            .iter()
+            .find_map(|portal| portal.partner(next))
+            .map_or(Some(next), step)
+    }
+}
+
+/// A pair of linked cells.
+///
+/// A snake that moves into one comes out of the other, still going the same way. Nothing is ever
+/// on a portal's cells: the snake's head jumps straight from the cell before one end to the cell
+/// after the other.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Portal(pub Coord, pub Coord);
+
+impl Portal {
+    /// If the coordinate is one end of the portal, returns the other end.
+    #[must_use]
+    pub fn partner(self, coord: Coord) -> Option<Coord> {
+        if coord == self.0 {
+            Some(self.1)
+        } else if coord == self.1 {
+            Some(self.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// The most direction changes that can be waiting in an [`InputQueue`].
+const INPUT_QUEUE_LEN: usize = 3;
+
+/// Direction changes that a player has pressed, but which haven't been given to their snake yet.
+///
+/// A snake can only turn once per step, so if a player quickly presses two keys (for example, up
+/// then left, to turn tightly), then the second turn must wait for the next step. Each key is
+/// checked against the direction that the snake will be facing after the turns already in the
+/// queue, rather than the direction it is facing now.
+pub struct InputQueue(CircularBuffer<Direction, INPUT_QUEUE_LEN>);
+
+impl InputQueue {
+    /// Creates an empty queue.
+    #[must_use]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(CircularBuffer::new())
+    }
+
+    /// Queues the direction change for a key, given the direction the snake is currently facing.
+    /// Keys that aren't a valid direction change are ignored, as are any keys once the queue is
+    /// full.
+    pub fn push_key(&mut self, current: Direction, key: Key) {
+        if self.0.len() == INPUT_QUEUE_LEN {
+            return;
+        }
+
+        let facing = self.0.iter().next_back().unwrap_or(current);
+        if let Some(dir) = facing.change_from_key(key) {
+            self.0.push(dir);
+        }
+    }
+
+    /// Takes the oldest direction change from the queue, to be given to [`GameState::step`].
+    pub const fn pop(&mut self) -> Option<Direction> {
+        self.0.pop()
+    }
+}
+
+/// Enumeration representing the four possible directions that the snake can be moving in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+impl Direction {
+    /// Returns the direction facing the other way.
+    #[must_use]
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::Right => Self::Left,
+            Self::Left => Self::Right,
+        }
+    }
+
+    /// Convert a keypress event into a direction for the snake, checking that the snake isn't
+    /// doubling back on itself or continuing in the same direction (the latter improves input
+    /// "feel").
+    #[must_use]
+    pub const fn change_from_key(self, key: Key) -> Option<Self> {
+        Some(match (self, key) {
+            (Self::Left | Self::Right, Key::Up | Key::Char(b'w')) => Self::Up,
+            (Self::Left | Self::Right, Key::Down | Key::Char(b's')) => Self::Down,
+            (Self::Up | Self::Down, Key::Right | Key::Char(b'd')) => Self::Right,
+            (Self::Up | Self::Down, Key::Left | Key::Char(b'a')) => Self::Left,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oca_term::Key;
+
+    use core::time::Duration;
+
+    use super::{
+        COMBO_TICKS, CRASH_PENALTY, CanvasSize, Coord, DeathCause, Direction, Fruit, FruitKind,
+        GOLDEN_POINTS, GameConfig, GameEvent, GameMode, GameState, InputQueue, PORTAL_PAIRS,
+        Portal, SHRINK_INTERVAL, SHRINK_LENGTH, STARTING_LENGTH, TIME_ATTACK_LIMIT,
+    };
+    use crate::{difficulty::Difficulty, level::Level};
+
+    /// Where the snake starts on the classic level.
+    const STARTING_POS: Coord = Coord {
+        x: 3,
+        y: CanvasSize::MIN.h / 2,
+    };
+
+    fn classic(seed: u64, config: GameConfig) -> GameState {
+        GameState::new(seed, config, &Level::classic())
+    }
+
+    /// Puts a special fruit onto the canvas, which expires on the given tick.
+    fn place(state: &mut GameState, coord: Coord, kind: FruitKind, expires: Option<u32>) {
+        state.fruits.push(Fruit {
+            coord,
+            kind,
+            expires,
+        });
+        state.bitboard.set(coord, true);
+    }
+
+    /// The cell directly in front of the snake, after it has moved `n` times.
+    const fn ahead(n: u16) -> Coord {
+        Coord {
+            x: STARTING_POS.x + n,
+            y: STARTING_POS.y,
+        }
+    }
+
+    #[test]
+    fn moves_right() {
+        let mut state = classic(0, GameConfig::default());
+        let events = state.step(&[None]);
+        let head = Coord {
+            x: STARTING_POS.x + 1,
+            y: STARTING_POS.y,
+        };
+        assert_eq!(
+            events,
+            [GameEvent::Moved {
+                snake: 0,
+                old: STARTING_POS,
+                head
+            }]
+        );
+        assert_eq!(state.snakes()[0].head(), head);
+        assert_eq!(state.snakes()[0].tail().count(), 2);
+    }
+
+    #[test]
+    fn grows_to_starting_length() {
+        let mut state = classic(0, GameConfig::default());
+        for _ in 0..STARTING_LENGTH - 1 {
+            assert!(
+                !state
+                    .step(&[None])
+                    .contains(&GameEvent::Trimmed(STARTING_POS))
+            );
+        }
+        assert_eq!(state.snakes()[0].tail().count(), STARTING_LENGTH);
+        assert!(
+            state
+                .step(&[None])
+                .contains(&GameEvent::Trimmed(STARTING_POS))
+        );
+        assert_eq!(state.snakes()[0].tail().count(), STARTING_LENGTH);
+    }
+
+    #[test]
+    fn dies_on_wall() {
+        let mut state = classic(0, GameConfig::default());
+        for _ in 0..=STARTING_POS.y {
+            assert!(!state.is_finished());
+            state.step(&[Some(Direction::Up)]);
+        }
+        assert!(state.is_finished());
+        assert_eq!(state.snakes()[0].head(), Coord { x: 3, y: 0 });
+        assert_eq!(state.score(), 0);
+        assert!(state.step(&[None]).is_empty());
+    }
+
+    #[test]
+    fn crashes_in_time_attack() {
+        let config = GameConfig {
+            mode: GameMode::TimeAttack,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, config);
+        state.snakes[0].score = 12;
+        for _ in 0..STARTING_POS.y {
+            state.step(&[Some(Direction::Up)]);
+        }
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::Crashed {
+            snake: 0,
+            cause: DeathCause::Wall,
+            head: STARTING_POS,
+        }));
+        assert!(!state.is_finished());
+        assert_eq!(state.score(), 12 - CRASH_PENALTY);
+        assert_eq!(state.snakes()[0].head(), STARTING_POS);
+        assert_eq!(state.snakes()[0].tail().count(), 1);
+
+        // The snake starts again in its starting direction.
+        state.step(&[None]);
+        assert_eq!(state.snakes()[0].head(), ahead(1));
+    }
+
+    #[test]
+    fn time_attack_runs_out() {
+        let config = GameConfig {
+            mode: GameMode::TimeAttack,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, config);
+        let mut events = Vec::new();
+        while !state.is_finished() {
+            // Go round in circles until the time runs out.
+            let dir = match state.ticks() % 4 {
+                0 => Direction::Up,
+                1 => Direction::Right,
+                2 => Direction::Down,
+                _ => Direction::Left,
+            };
+            events = state.step(&[Some(dir)]);
+        }
+        assert_eq!(events.last(), Some(&GameEvent::TimeUp));
+        assert!(state.elapsed >= TIME_ATTACK_LIMIT);
+    }
+
+    #[test]
+    fn closes_in_for_survival() {
+        let config = GameConfig {
+            mode: GameMode::Survival,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, config);
+        let size = state.size();
+        assert!(
+            state
+                .step(&[None])
+                .iter()
+                .all(|event| !matches!(event, GameEvent::NewWall(_)))
+        );
+
+        state.elapsed = SHRINK_INTERVAL;
+        let events = state.step(&[None]);
+        assert!(!state.is_finished());
+
+        // The whole edge of the canvas is now a wall, and fruits are kept off it.
+        let ring = 2 * (size.w + size.h) as usize - 4;
+        let walls = events
+            .iter()
+            .filter(|event| matches!(event, GameEvent::NewWall(_)))
+            .count();
+        assert_eq!(walls, ring);
+        assert_eq!(state.walls().len(), ring);
+        for &coord in state.walls() {
+            assert!(state.bitboard.get(coord));
+        }
+        for fruit in state.fruits() {
+            assert!(!state.walls().contains(&fruit.coord));
+        }
+
+        // Head for the top, and let the next ring close in on the snake.
+        while state.snakes()[0].head().y > 2 {
+            state.step(&[Some(Direction::Up)]);
+        }
+        state.elapsed = SHRINK_INTERVAL * 2;
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::Died {
+            snake: 0,
+            cause: DeathCause::Wall
+        }));
+        assert!(state.is_finished());
+    }
+
+    #[test]
+    fn stops_closing_in() {
+        let config = GameConfig {
+            mode: GameMode::Survival,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, config);
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            state.shrink(&mut events);
+        }
+        // The smallest canvas is 19 cells high, so 6 rings leave 7 rows in the middle.
+        assert_eq!(state.rings, 6);
+    }
+
+    #[test]
+    fn places_portals() {
+        let config = GameConfig {
+            mode: GameMode::Portals,
+            ..GameConfig::default()
+        };
+        for seed in 0..20 {
+            let state = classic(seed, config);
+            let size = state.size();
+            assert_eq!(state.portals().len(), PORTAL_PAIRS);
+            for portal in state.portals() {
+                for end in [portal.0, portal.1] {
+                    assert!(end.x > 0 && end.x < size.w - 1 && end.y > 0 && end.y < size.h - 1);
+                    assert_ne!(end.y, STARTING_POS.y);
+                    // Fruits are never placed on a portal.
+                    assert!(state.bitboard.get(end));
+                    assert!(state.fruit_at(end).is_none());
+                }
+            }
+        }
+        assert!(classic(0, GameConfig::default()).portals().is_empty());
+    }
+
+    #[test]
+    fn goes_through_portals() {
+        let mut state = classic(0, GameConfig::default());
+        let exit = Coord { x: 10, y: 3 };
+        state.portals.push(Portal(ahead(2), exit));
+        state.bitboard.set(ahead(2), true);
+        state.bitboard.set(exit, true);
+
+        state.step(&[None]);
+        let head = Coord { x: 11, y: 3 };
+        assert_eq!(
+            state.step(&[None]),
+            [GameEvent::Moved {
+                snake: 0,
+                old: ahead(1),
+                head
+            }]
+        );
+        assert_eq!(state.snakes()[0].head(), head);
+
+        // The snake keeps going the same way, with its tail split between the portals.
+        state.step(&[None]);
+        let tail: Vec<_> = state.snakes()[0].tail().collect();
+        assert_eq!(tail, [STARTING_POS, ahead(1), head, Coord { x: 12, y: 3 }]);
+
+        // Going into the other end comes out of the first, still going the same way.
+        state.step(&[Some(Direction::Down)]);
+        state.step(&[Some(Direction::Left)]);
+        state.step(&[Some(Direction::Left)]);
+        state.step(&[Some(Direction::Up)]);
+        let head = Coord {
+            x: ahead(2).x,
+            y: STARTING_POS.y - 1,
+        };
+        assert_eq!(state.snakes()[0].head(), head);
+        assert!(!state.is_finished());
+    }
+
+    #[test]
+    fn dies_on_level_wall() {
+        // The "Box" level has a wall all along the top of the canvas, except in the middle.
+        let level = crate::level::bundled().find(|l| l.name == "Box").unwrap();
+        let mut state = GameState::new(
+            0,
+            GameConfig {
+                wrap: true,
+                ..GameConfig::default()
+            },
+            &level,
+        );
+        for _ in 1..STARTING_POS.y {
+            state.step(&[Some(Direction::Up)]);
+        }
+        assert!(!state.is_finished());
+        assert_eq!(
+            state.step(&[None]),
+            [GameEvent::Died {
+                snake: 0,
+                cause: DeathCause::Wall
+            }]
+        );
+        assert_eq!(state.snakes()[0].head(), Coord { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut state = classic(
+            0,
+            GameConfig {
+                wrap: true,
+                ..GameConfig::default()
+            },
+        );
+        for _ in 0..=STARTING_POS.y {
+            state.step(&[Some(Direction::Up)]);
+        }
+        assert!(!state.is_finished());
+        assert_eq!(
+            state.snakes()[0].head(),
+            Coord {
+                x: STARTING_POS.x,
+                y: CanvasSize::MIN.h - 1
+            }
+        );
+    }
+
+    #[test]
+    fn dies_on_tail() {
+        let mut state = classic(0, GameConfig::default());
+        for _ in 0..4 {
+            state.step(&[None]);
+        }
+        state.step(&[Some(Direction::Up)]);
+        state.step(&[Some(Direction::Left)]);
+        assert_eq!(
+            state.step(&[Some(Direction::Down)]),
+            [GameEvent::Died {
+                snake: 0,
+                cause: DeathCause::Tail
+            }]
+        );
+    }
+
+    #[test]
+    fn eats_fruit() {
+        let mut state = classic(0, GameConfig::default());
+        // The centre fruit of the 'X' is at (21, 9), in line with the snake.
+        let mut ate = 0;
+        while state.snakes()[0].head().x < 21 {
+            ate += state
+                .step(&[None])
+                .iter()
+                .filter(|e| matches!(e, GameEvent::AteFruit { .. }))
+                .count();
+        }
+        assert_eq!(ate, 1);
+        assert_eq!(state.score(), 1);
+        assert!(
+            state
+                .fruits()
+                .iter()
+                .all(|f| f.coord != Coord { x: 21, y: 9 })
+        );
+        let normal = state
+            .fruits()
+            .iter()
+            .filter(|f| f.kind == FruitKind::Normal);
+        assert_eq!(normal.count(), 5);
+    }
+
+    #[test]
+    fn golden_fruit() {
+        let mut state = classic(0, GameConfig::default());
+        place(&mut state, ahead(1), FruitKind::Golden, None);
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::AteFruit {
+            snake: 0,
+            kind: FruitKind::Golden
+        }));
+        assert_eq!(state.score(), GOLDEN_POINTS);
+        assert_eq!(state.fruits().len(), 5);
+
+        // This one isn't eaten in time.
+        place(&mut state, ahead(5), FruitKind::Golden, Some(3));
+        state.step(&[None]);
+        let events = state.step(&[None]);
+        assert!(events.contains(&GameEvent::FruitExpired(ahead(5))));
+        assert_eq!(state.fruits().len(), 5);
+        for _ in 0..3 {
+            state.step(&[None]);
+        }
+        assert!(!state.is_finished());
+        assert_eq!(state.score(), GOLDEN_POINTS);
+    }
+
+    #[test]
+    fn shrink_fruit() {
+        let mut state = classic(0, GameConfig::default());
+        for _ in 0..STARTING_LENGTH {
+            state.step(&[None]);
+        }
+        place(
+            &mut state,
+            ahead(STARTING_LENGTH as u16 + 1),
+            FruitKind::Shrink,
+            None,
+        );
+        let events = state.step(&[None]);
+        let trimmed = events
+            .iter()
+            .filter(|e| matches!(e, GameEvent::Trimmed(_)))
+            .count();
+        assert_eq!(trimmed, SHRINK_LENGTH + 1);
+        assert_eq!(
+            state.snakes()[0].tail().count(),
+            STARTING_LENGTH - SHRINK_LENGTH
+        );
+        assert_eq!(state.score(), 1);
+    }
+
+    #[test]
+    fn slow_down_fruit() {
+        let ms = Duration::from_millis;
+        let mut state = classic(
+            0,
+            GameConfig {
+                wrap: true,
+                ..GameConfig::default()
+            },
+        );
+        state.step_time = ms(100);
+        state.step_times = vec![ms(140), ms(120)];
+        place(&mut state, ahead(1), FruitKind::SlowDown, None);
+
+        state.step(&[None]);
+        assert_eq!(state.step_time(), ms(140));
+        // The slow-down lasts for five seconds at the slower speed.
+        for _ in 1..5000 / 140 {
+            state.step(&[None]);
+            assert_eq!(state.step_time(), ms(140));
+        }
+        state.step(&[None]);
+        assert!(state.step_time() < ms(100));
+    }
+
+    #[test]
+    fn difficulty_changes_rules() {
+        for difficulty in Difficulty::ALL {
+            let settings = difficulty.settings();
+            let mut state = classic(
+                0,
+                GameConfig {
+                    difficulty,
+                    ..GameConfig::default()
+                },
+            );
+            assert_eq!(state.fruits().len(), settings.fruits);
+            assert_eq!(state.step_time(), settings.step_time);
+
+            for _ in 0..=settings.starting_length {
+                state.step(&[None]);
+            }
+            assert_eq!(state.snakes()[0].tail().count(), settings.starting_length);
+        }
+    }
+
+    #[test]
+    fn combo_multiplier() {
+        let mut state = classic(
+            0,
+            GameConfig {
+                wrap: true,
+                combo: true,
+                ..GameConfig::default()
+            },
+        );
+        // Three fruits in a row: the second and third are worth double and triple.
+        for n in 1..=3 {
+            place(&mut state, ahead(n), FruitKind::Golden, None);
+        }
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.extend(state.step(&[None]));
+        }
+        assert!(events.contains(&GameEvent::Multiplier {
+            snake: 0,
+            multiplier: 3
+        }));
+        assert_eq!(state.score(), GOLDEN_POINTS * (1 + 2 + 3));
+
+        // The multiplier drops back down, one step at a time, while the snake loops around an
+        // empty column.
+        state.step(&[Some(Direction::Up)]);
+        for _ in 1..COMBO_TICKS {
+            state.step(&[None]);
+        }
+        assert_eq!(state.snakes[0].multiplier, 2);
+        for _ in 0..COMBO_TICKS {
+            state.step(&[None]);
+        }
+        assert_eq!(state.snakes[0].multiplier, 1);
+        assert!(!state.is_finished());
+    }
+
+    #[test]
+    fn longer_snake_wins_head_to_head() {
+        let two_player = GameConfig {
+            two_player: true,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, two_player);
+        assert_eq!(
+            state.snakes()[1].head(),
+            Coord {
+                x: 24,
+                y: STARTING_POS.y
+            }
+        );
+
+        // The snakes drive straight at each other, but the second snake eats the centre fruit of
+        // the 'X' on the way, so it is longer when they meet.
+        let mut events = Vec::new();
+        while !state.is_finished() {
+            events = state.step(&[None, None]);
+        }
+        assert_eq!(
+            events,
+            [GameEvent::Died {
+                snake: 0,
+                cause: DeathCause::Snake
+            }]
+        );
+        assert!(!state.snakes()[0].is_alive());
+        assert!(state.snakes()[1].is_alive());
+        assert_eq!(state.snakes()[1].score(), 1);
+        assert_eq!(state.winner(), Some(1));
+    }
+
+    #[test]
+    fn higher_score_wins_when_both_survive() {
+        let two_player = GameConfig {
+            two_player: true,
+            ..GameConfig::default()
+        };
+        let mut state = classic(0, two_player);
+        state.finished = true;
+        assert_eq!(state.winner(), None);
+
+        // Golden fruit (and combos) make score and length differ, and score is what counts.
+        state.snakes[0].len += 5;
+        state.snakes[1].score = 3;
+        assert_eq!(state.winner(), Some(1));
+    }
+
+    #[test]
+    fn queues_turns() {
+        let mut queue = InputQueue::new();
+        // Up then left is a valid tight turn, even though left is invalid while facing right.
+        queue.push_key(Direction::Right, Key::Up);
+        queue.push_key(Direction::Right, Key::Left);
+        // Left again is not a change of direction, and so it is ignored.
+        queue.push_key(Direction::Right, Key::Char(b'a'));
+        queue.push_key(Direction::Right, Key::Down);
+        // The queue is full, so this is dropped.
+        queue.push_key(Direction::Right, Key::Right);
+
+        assert_eq!(queue.pop(), Some(Direction::Up));
+        assert_eq!(queue.pop(), Some(Direction::Left));
+        assert_eq!(queue.pop(), Some(Direction::Down));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn deterministic() {
+        let play = |seed| {
+            let mut state = classic(seed, GameConfig::default());
+            let mut events = Vec::new();
+            // Loop around the right-hand side of the canvas, eating the fruits on the way.
+            for (dir, n) in [
+                (Direction::Right, 21),
+                (Direction::Up, 6),
+                (Direction::Left, 20),
+                (Direction::Down, 8),
+            ] {
+                events.extend(state.step(&[Some(dir)]));
+                for _ in 1..n {
+                    events.extend(state.step(&[None]));
+                }
+            }
+            (events, state.fruits().to_vec())
+        };
+
+        assert_eq!(play(42), play(42));
+        assert_ne!(play(42), play(43));
+    }
+}
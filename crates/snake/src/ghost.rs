@@ -0,0 +1,82 @@
+//! The ghost of a previous game, which the player races against.
+//!
+//! When a single-player game starts, the best saved [`Replay`] with the same seed, rules and level
+//! is played back alongside it, in lockstep: each time the real game steps, so does the ghost's.
+//! Only the ghost's snake is shown, faintly and on the UI's ghost layer (see
+//! [`GameUi::draw_ghost`]), so it passes underneath the real snake and fruits without colliding
+//! with them. Games only share a seed when it is fixed with `SNAKESEED`, so that is the only time
+//! that the saved replays are searched for a ghost.
+
+use oca_io::Result;
+use oca_term::Pixel;
+
+use snakegame::{
+    controller::{Controller as _, Scripted},
+    game::{GameConfig, GameEvent, GameState},
+    level::Level,
+    replay::Replay,
+};
+
+use crate::{snake::SNAKE_COLORS, ui::GameUi};
+
+/// How the ghost's snake is drawn: a faint version of the first player's colour.
+const GHOST_PIXEL: Pixel = Pixel::faint(SNAKE_COLORS[0]);
+
+/// A previous game, played back alongside the real one.
+pub struct Ghost {
+    state: GameState,
+    controller: Scripted,
+    /// The number of ticks that the previous game lasted.
+    ticks: u32,
+}
+
+impl Ghost {
+    /// Starts the ghost of the best saved game with the given seed, rules and level, if there is
+    /// one. Two-player games don't have a ghost.
+    #[must_use]
+    pub fn find(seed: u64, config: GameConfig, level: &Level) -> Option<Self> {
+        if config.two_player {
+            return None;
+        }
+        let replay = Replay::best(seed, config, level)?;
+        Some(Self {
+            state: GameState::new(replay.seed, replay.config, level),
+            controller: replay.controller(0),
+            ticks: replay.ticks,
+        })
+    }
+
+    /// Draws the ghost's snake where it starts.
+    pub fn draw(&self, ui: &mut GameUi) -> Result<()> {
+        ui.draw_ghost(self.state.snakes()[0].head(), GHOST_PIXEL)
+    }
+
+    /// Steps the ghost's game once, moving its snake on the ghost layer. Once the ghost's game is
+    /// over, its snake is erased instead, and `false` is returned.
+    pub fn step(&mut self, ui: &mut GameUi) -> Result<bool> {
+        if self.state.is_finished() || self.state.ticks() >= self.ticks {
+            self.erase(ui)?;
+            return Ok(false);
+        }
+
+        let input = self.controller.next(&self.state, 0);
+        for event in self.state.step(&[input]) {
+            match event {
+                GameEvent::Moved { head, .. } | GameEvent::Crashed { head, .. } => {
+                    ui.draw_ghost(head, GHOST_PIXEL)?;
+                }
+                GameEvent::Trimmed(coord) => ui.draw_ghost(coord, Pixel::Clear)?,
+                _ => (),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Erases the ghost's snake.
+    pub fn erase(&self, ui: &mut GameUi) -> Result<()> {
+        for coord in self.state.snakes()[0].tail() {
+            ui.draw_ghost(coord, Pixel::Clear)?;
+        }
+        Ok(())
+    }
+}
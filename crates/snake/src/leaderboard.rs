@@ -1,47 +1,210 @@
+mod local;
 mod network;
 
 use core::fmt::{self, Write};
 use core::str::FromStr;
+use std::collections::BTreeMap;
 use std::env;
 use std::net::SocketAddrV4;
 
+use oca_io::network::{LeaderboardEntries, LeaderboardEntry};
 use oca_io::{Error, Result};
-use oca_io::{network::LeaderboardEntries, socket::Socket};
 use oca_term::{Box, Draw, DrawCtx};
 
+use local::LocalStore;
+use network::Server;
+
+use snakegame::{
+    difficulty::Difficulty,
+    game::{GameConfig, GameMode},
+};
+
+/// A leaderboard with no entries.
+const EMPTY_ENTRIES: LeaderboardEntries = [LeaderboardEntry(*b"---", 0); 10];
+
+/// The number of leaderboards for each scoring model. Combo scoring uses the leaderboards after
+/// these.
+const SCORING_BOARDS: u8 = 8;
+
+/// The first time attack leaderboard, after the leaderboards for both scoring models. There is one
+/// for each difficulty, and then one for each difficulty with combo scoring.
+const TIME_ATTACK_BOARDS: u8 = SCORING_BOARDS * 2;
+
+/// The first survival leaderboard, after the time attack leaderboards, which are laid out the
+/// same way.
+const SURVIVAL_BOARDS: u8 = TIME_ATTACK_BOARDS + 8;
+
+/// The first leaderboard for games with portals, after the survival leaderboards, which are laid
+/// out the same way.
+const PORTAL_BOARDS: u8 = SURVIVAL_BOARDS + 8;
+
+/// Returns the leaderboard that games with the given rules are scored on, and the title to show
+/// above it. Each combination of walls, difficulty and scoring has its own leaderboard, so that
+/// scores are comparable. Combo scoring leaderboards have the same titles, marked with a `×`.
+/// The other modes are always played without wrap-around, and have leaderboards of their own.
+pub const fn board_for(config: GameConfig) -> (u8, &'static str) {
+    match config.mode {
+        GameMode::Classic => (),
+        GameMode::Survival => {
+            return match (config.difficulty, config.combo) {
+                (Difficulty::Normal, false) => (SURVIVAL_BOARDS, "SURVIVAL"),
+                (Difficulty::Easy, false) => (SURVIVAL_BOARDS + 1, "EASY SURV"),
+                (Difficulty::Hard, false) => (SURVIVAL_BOARDS + 2, "HARD SURV"),
+                (Difficulty::Insane, false) => (SURVIVAL_BOARDS + 3, "INSANE SURV"),
+                (Difficulty::Normal, true) => (SURVIVAL_BOARDS + 4, "× SURVIVAL"),
+                (Difficulty::Easy, true) => (SURVIVAL_BOARDS + 5, "× EASY SURV"),
+                (Difficulty::Hard, true) => (SURVIVAL_BOARDS + 6, "× HARD SURV"),
+                (Difficulty::Insane, true) => (SURVIVAL_BOARDS + 7, "× INSANE SURV"),
+            };
+        }
+        GameMode::Portals => {
+            return match (config.difficulty, config.combo) {
+                (Difficulty::Normal, false) => (PORTAL_BOARDS, "PORTALS"),
+                (Difficulty::Easy, false) => (PORTAL_BOARDS + 1, "EASY PORTAL"),
+                (Difficulty::Hard, false) => (PORTAL_BOARDS + 2, "HARD PORTAL"),
+                (Difficulty::Insane, false) => (PORTAL_BOARDS + 3, "INSANE PORTAL"),
+                (Difficulty::Normal, true) => (PORTAL_BOARDS + 4, "× PORTALS"),
+                (Difficulty::Easy, true) => (PORTAL_BOARDS + 5, "× EASY PORTAL"),
+                (Difficulty::Hard, true) => (PORTAL_BOARDS + 6, "× HARD PORTAL"),
+                (Difficulty::Insane, true) => (PORTAL_BOARDS + 7, "× INSANE PORT"),
+            };
+        }
+        GameMode::TimeAttack => {
+            return match (config.difficulty, config.combo) {
+                (Difficulty::Normal, false) => (TIME_ATTACK_BOARDS, "TIME ATTACK"),
+                (Difficulty::Easy, false) => (TIME_ATTACK_BOARDS + 1, "EASY TIME"),
+                (Difficulty::Hard, false) => (TIME_ATTACK_BOARDS + 2, "HARD TIME"),
+                (Difficulty::Insane, false) => (TIME_ATTACK_BOARDS + 3, "INSANE TIME"),
+                (Difficulty::Normal, true) => (TIME_ATTACK_BOARDS + 4, "× TIME ATTACK"),
+                (Difficulty::Easy, true) => (TIME_ATTACK_BOARDS + 5, "× EASY TIME"),
+                (Difficulty::Hard, true) => (TIME_ATTACK_BOARDS + 6, "× HARD TIME"),
+                (Difficulty::Insane, true) => (TIME_ATTACK_BOARDS + 7, "× INSANE TIME"),
+            };
+        }
+    }
+
+    let (board, title) = match (config.difficulty, config.wrap) {
+        (Difficulty::Normal, false) => (0, "LEADERBOARD"),
+        (Difficulty::Normal, true) => (1, "WRAP-AROUND"),
+        (Difficulty::Easy, false) => (2, "EASY"),
+        (Difficulty::Easy, true) => (3, "EASY WRAP"),
+        (Difficulty::Hard, false) => (4, "HARD"),
+        (Difficulty::Hard, true) => (5, "HARD WRAP"),
+        (Difficulty::Insane, false) => (6, "INSANE"),
+        (Difficulty::Insane, true) => (7, "INSANE WRAP"),
+    };
+
+    if !config.combo {
+        return (board, title);
+    }
+    let title = match board {
+        0 => "× LEADERBOARD",
+        1 => "× WRAP-AROUND",
+        2 => "× EASY",
+        3 => "× EASY WRAP",
+        4 => "× HARD",
+        5 => "× HARD WRAP",
+        6 => "× INSANE",
+        _ => "× INSANE WRAP",
+    };
+    (board + SCORING_BOARDS, title)
+}
+
+/// Where the leaderboards come from.
+enum Store {
+    /// A leaderboard server, shared between machines.
+    Server(Server),
+    /// Files on this machine, for when there is no server.
+    Local(LocalStore),
+}
+
 pub struct Leaderboard {
+    /// The entries of the leaderboard currently being shown.
     pub entries: LeaderboardEntries,
-    pub score: Option<u8>,
-    sock: Socket,
-    sock_is_conn: bool,
-    addr: SocketAddrV4,
+    pub score: Option<u16>,
+    /// The leaderboard currently being shown, and its title.
+    board: (u8, &'static str),
+    /// The latest entries of every leaderboard that the server has sent us (or that have been
+    /// saved on this machine).
+    boards: BTreeMap<u8, LeaderboardEntries>,
+    store: Store,
     you_row: Option<u16>,
     has_10_pos: bool,
 }
 
 impl Leaderboard {
+    /// Connects to the leaderboard server given by `SNAKEADDR`. Without one, the leaderboards
+    /// are kept in files on this machine instead (see [`local`]), so there is only no leaderboard
+    /// if there is nowhere to keep them.
     pub fn init() -> Option<Result<Self>> {
-        let addr = env::var("SNAKEADDR").ok()?;
-        let Ok(addr) = SocketAddrV4::from_str(&addr) else {
-            return Some(Err(Error::Other("invalid SNAKEADDR address/port")));
-        };
-
-        let (entries, sock) = match network::connect_tcp(addr) {
-            Ok(val) => val,
-            Err(err) => return Some(Err(err)),
+        let (store, boards) = if let Ok(addr) = env::var("SNAKEADDR") {
+            let Ok(addr) = SocketAddrV4::from_str(&addr) else {
+                return Some(Err(Error::Other("invalid SNAKEADDR address/port")));
+            };
+            match Server::connect(addr) {
+                Ok((server, (board, entries))) => {
+                    (Store::Server(server), BTreeMap::from([(board, entries)]))
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        } else {
+            let local = LocalStore::open()?;
+            let boards = local.load();
+            (Store::Local(local), boards)
         };
 
+        let board = board_for(GameConfig::default());
         Some(Ok(Self {
-            entries,
+            entries: boards.get(&board.0).copied().unwrap_or(EMPTY_ENTRIES),
             score: None,
-            sock,
-            sock_is_conn: true,
-            addr,
+            board,
+            boards,
+            store,
             you_row: None,
             has_10_pos: true,
         }))
     }
 
+    /// Returns `true` if games can be submitted to the leaderboard: always for local leaderboards,
+    /// and while connected for a server.
+    pub const fn has_conn(&self) -> bool {
+        match &self.store {
+            Store::Server(server) => server.has_conn(),
+            Store::Local(_) => true,
+        }
+    }
+
+    /// Submits a game to the leaderboard currently being shown. Local leaderboards are updated
+    /// (and saved) straight away, whereas the server sends back the updated leaderboard.
+    pub fn send_game(&mut self, name: [u8; 3], score: u16) -> Result<()> {
+        let board = self.board.0;
+        match &mut self.store {
+            Store::Server(server) => server.send_game(name, score, board),
+            Store::Local(local) => {
+                self.entries = local::insert(&self.entries, LeaderboardEntry(name, score));
+                self.boards.insert(board, self.entries);
+                // If the file can't be saved, the game is still on the leaderboard until the
+                // program exits, which is better than stopping here.
+                let _ = local.save(board, &self.entries);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads an updated leaderboard from the server, if it has sent one. Local leaderboards are
+    /// only changed by [`Self::send_game`], so there is never anything to read.
+    fn read_leaderboard(&mut self, block: bool) -> Option<(u8, LeaderboardEntries)> {
+        match &mut self.store {
+            Store::Server(server) => server.read_leaderboard(block),
+            Store::Local(_) => None,
+        }
+    }
+
+    /// Returns the title of the leaderboard currently being shown.
+    pub const fn title(&self) -> &'static str {
+        self.board.1
+    }
+
     /// Redraw all the leaderboard entries, using the provided [`DrawCtx`].
     ///
     /// This function recalculates (and redraws) the position of the "YOU" row.
@@ -143,10 +306,11 @@ impl Draw for &mut Leaderboard {
     fn draw<W: fmt::Write>(self, ctx: &mut oca_term::DrawCtx<W>) -> Result<()> {
         // TODO: this could be fastdraw'ed
         ctx.draw(0, 0, Box::new(13, 12).with_horz_lines(&[1]).with_fastdraw())?;
-        ctx.draw(2, 1, "\x1B[1;34mLEADERBOARD\x1B[0m")?;
+        ctx.draw(1, 1, format!("\x1B[1;34m{:^13}\x1B[0m", self.board.1))?;
         for i in 1..=10 {
             ctx.draw(2, 2 + i, format!("{i:0>2}."))?;
         }
+        self.has_10_pos = true;
 
         self.draw_entries(ctx)?;
 
@@ -167,8 +331,15 @@ impl Draw for &mut Leaderboard {
                 self.draw_entries(ctx)
             }
             LeaderboardUpdate::Network(block, force) => {
-                if let Some(entries) = self.read_leaderboard(block) {
-                    self.entries = entries;
+                let changed = if let Some((board, entries)) = self.read_leaderboard(block) {
+                    self.boards.insert(board, entries);
+                    board == self.board.0
+                } else {
+                    false
+                };
+
+                if changed {
+                    self.entries = self.boards[&self.board.0];
                     self.draw_entries(ctx)?;
                 } else if force {
                     self.draw_entries(ctx)?;
@@ -177,6 +348,16 @@ impl Draw for &mut Leaderboard {
                 Ok(())
             }
             LeaderboardUpdate::Redraw => self.draw_entries(ctx),
+            LeaderboardUpdate::Board(board, title) => {
+                if self.board == (board, title) {
+                    return Ok(());
+                }
+
+                self.board = (board, title);
+                self.entries = self.boards.get(&board).copied().unwrap_or(EMPTY_ENTRIES);
+                ctx.draw(1, 1, format!("\x1B[1;34m{title:^13}\x1B[0m"))?;
+                self.draw_entries(ctx)
+            }
             LeaderboardUpdate::FillPlayer(name) => {
                 let (you_row, score) = (self.you_row.unwrap(), self.score.unwrap());
                 let name = std::str::from_utf8(&name).unwrap();
@@ -191,8 +372,10 @@ impl Draw for &mut Leaderboard {
 }
 
 pub enum LeaderboardUpdate {
-    Score(u8),
+    Score(u16),
     Network(bool, bool),
     Redraw,
     FillPlayer([u8; 3]),
+    /// Switch to showing a different leaderboard, given its ID and title (see [`board_for`]).
+    Board(u8, &'static str),
 }
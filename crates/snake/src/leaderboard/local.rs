@@ -0,0 +1,208 @@
+//! Leaderboards kept in files on this machine, for when there is no leaderboard server.
+//!
+//! The files are stored in the game's state directory (see [`snakegame::state_dir`]), in the same
+//! format as the server's (see `snake-server`): `leaderboard` for the classic game and
+//! `leaderboard-<id>` for every other leaderboard. Each file starts with the magic bytes `SLB2`,
+//! followed by the entries from best to worst: the player's name (three ASCII letters or digits)
+//! and their score, as a big-endian `u16`. Only the top ten entries are kept. A file that isn't
+//! in this format is ignored, and replaced the next time a game is added to its leaderboard.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use oca_io::{
+    Error, Result,
+    network::{LeaderboardEntries, LeaderboardEntry},
+};
+
+use super::EMPTY_ENTRIES;
+
+/// Leaderboard files start with these bytes.
+const FILE_MAGIC: &[u8; 4] = b"SLB2";
+
+/// The directory that the leaderboard files are kept in.
+pub(super) struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    /// Finds the directory for leaderboard files, or returns [`None`] if there is nowhere to keep
+    /// them (see [`snakegame::state_dir`]).
+    pub(super) fn open() -> Option<Self> {
+        Some(Self {
+            dir: snakegame::state_dir()?,
+        })
+    }
+
+    /// Reads every leaderboard that has been saved. A missing directory just means that no games
+    /// have been saved yet, and files that can't be read (or are invalid) are skipped, so that
+    /// those leaderboards start again from empty.
+    pub(super) fn load(&self) -> BTreeMap<u8, LeaderboardEntries> {
+        let Ok(files) = fs::read_dir(&self.dir) else {
+            return BTreeMap::new();
+        };
+
+        files
+            .filter_map(|file| {
+                let file = file.ok()?;
+                let board = board_from_file_name(file.file_name().to_str()?)?;
+                let entries = from_bytes(&fs::read(file.path()).ok()?).ok()?;
+                Some((board, entries))
+            })
+            .collect()
+    }
+
+    /// Writes a leaderboard's entries to its file (see [`snakegame::write_state_file`]).
+    pub(super) fn save(&self, board: u8, entries: &LeaderboardEntries) -> Result<()> {
+        snakegame::write_state_file(&self.dir.join(board_file_name(board)), &to_bytes(entries))
+            .map_err(|_| Error::Other("failed to save leaderboard file"))
+    }
+}
+
+/// Inserts a game into a leaderboard, after any games with the same score, and returns the new
+/// top ten entries.
+pub(super) fn insert(entries: &LeaderboardEntries, game: LeaderboardEntry) -> LeaderboardEntries {
+    let played = entries.iter().take_while(|entry| entry.0 != *b"---");
+    let mut games = played.copied().collect::<Vec<_>>();
+    let pos = games.partition_point(|entry| entry.1 >= game.1);
+    games.insert(pos, game);
+
+    let mut new = EMPTY_ENTRIES;
+    for (entry, game) in new.iter_mut().zip(games) {
+        *entry = game;
+    }
+    new
+}
+
+/// Returns the name of the file that stores the given leaderboard.
+fn board_file_name(board: u8) -> String {
+    if board == 0 {
+        "leaderboard".to_string()
+    } else {
+        format!("leaderboard-{board}")
+    }
+}
+
+/// The inverse of [`board_file_name`].
+fn board_from_file_name(name: &str) -> Option<u8> {
+    match name {
+        "leaderboard" => Some(0),
+        _ => name.strip_prefix("leaderboard-")?.parse().ok(),
+    }
+}
+
+/// Parses a leaderboard file. The file is invalid unless it has at most ten entries, each with a
+/// valid name, in order from best to worst.
+fn from_bytes(bytes: &[u8]) -> Result<LeaderboardEntries> {
+    let games = bytes
+        .strip_prefix(FILE_MAGIC)
+        .filter(|games| games.len().is_multiple_of(5))
+        .ok_or(Error::Other("invalid leaderboard file"))?
+        .chunks(5)
+        .map(|bytes| {
+            let name = bytes[0..3].try_into().unwrap();
+            LeaderboardEntry(name, u16::from_be_bytes([bytes[3], bytes[4]]))
+        })
+        .collect::<Vec<_>>();
+
+    let valid_name = |name: [u8; 3]| name == *b"---" || name.iter().all(u8::is_ascii_alphanumeric);
+    if games.len() > EMPTY_ENTRIES.len()
+        || !games.iter().all(|game| valid_name(game.0))
+        || !games.is_sorted_by(|a, b| a.1 >= b.1)
+    {
+        return Err(Error::Other("invalid leaderboard file"));
+    }
+
+    let mut new = EMPTY_ENTRIES;
+    for (entry, game) in new.iter_mut().zip(games) {
+        *entry = game;
+    }
+    Ok(new)
+}
+
+fn to_bytes(entries: &LeaderboardEntries) -> Vec<u8> {
+    let mut bytes = FILE_MAGIC.to_vec();
+    for entry in entries.iter().take_while(|entry| entry.0 != *b"---") {
+        bytes.extend_from_slice(&entry.0);
+        bytes.extend_from_slice(&entry.1.to_be_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use oca_io::network::LeaderboardEntry;
+
+    use super::{EMPTY_ENTRIES, LocalStore, from_bytes, insert, to_bytes};
+
+    #[test]
+    fn keeps_top_ten() {
+        let mut entries = EMPTY_ENTRIES;
+        for (idx, score) in [20, 50, 20, 10, 5, 40, 30, 60, 70, 80, 90]
+            .into_iter()
+            .enumerate()
+        {
+            entries = insert(&entries, LeaderboardEntry([b'A' + idx as u8; 3], score));
+        }
+
+        let scores = entries.map(|entry| entry.1);
+        assert_eq!(scores, [90, 80, 70, 60, 50, 40, 30, 20, 20, 10]);
+        // Ties go to whoever got there first.
+        assert_eq!(entries[7].0, *b"AAA");
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = env::temp_dir().join(format!("snake-lb-{}", process::id()));
+        let store = LocalStore { dir: dir.clone() };
+        assert!(store.load().is_empty());
+
+        let entries = insert(&EMPTY_ENTRIES, LeaderboardEntry(*b"BOB", 300));
+        store.save(0, &entries).unwrap();
+        store.save(17, &EMPTY_ENTRIES).unwrap();
+        fs::write(dir.join("notes"), b"not a leaderboard").unwrap();
+
+        // A broken file only loses its own leaderboard.
+        fs::write(dir.join("leaderboard-3"), b"SLB2BO").unwrap();
+
+        let boards = store.load();
+        assert_eq!(boards.keys().copied().collect::<Vec<_>>(), [0, 17]);
+        assert_eq!(boards[&0][0], LeaderboardEntry(*b"BOB", 300));
+        assert_eq!(boards[&0][1], EMPTY_ENTRIES[0]);
+        assert_eq!(boards[&17], EMPTY_ENTRIES);
+        assert!(!dir.join("leaderboard.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut entries = EMPTY_ENTRIES;
+        for (name, score) in [(b"AMY", 90), (b"B0B", 40), (b"CAL", 40)] {
+            entries = insert(&entries, LeaderboardEntry(*name, score));
+        }
+        let bytes = to_bytes(&entries);
+        assert_eq!(from_bytes(&bytes).unwrap(), entries);
+
+        // Names must be letters or digits.
+        let mut bad_name = bytes.clone();
+        bad_name[5] = b' ';
+        assert!(from_bytes(&bad_name).is_err());
+
+        // The entries must be from best to worst.
+        let mut unsorted = bytes.clone();
+        unsorted[8] = 0;
+        assert!(from_bytes(&unsorted).is_err());
+
+        // There can't be more than ten entries.
+        let mut too_long = bytes[..4].to_vec();
+        for _ in 0..11 {
+            too_long.extend_from_slice(b"DAN\0\x01");
+        }
+        assert!(from_bytes(&too_long[..4 + 50]).is_ok());
+        assert!(from_bytes(&too_long).is_err());
+    }
+}
@@ -7,10 +7,28 @@ use oca_io::{
     socket::Socket,
 };
 
-use super::Leaderboard;
+/// A connection to a leaderboard server, given by `SNAKEADDR`.
+pub(super) struct Server {
+    sock: Socket,
+    sock_is_conn: bool,
+    addr: SocketAddrV4,
+}
+
+impl Server {
+    /// Connects to the server, returning the connection and the first leaderboard that it sends.
+    pub(super) fn connect(addr: SocketAddrV4) -> Result<(Self, (u8, LeaderboardEntries))> {
+        let (lb, sock) = connect_tcp(addr)?;
+        Ok((
+            Self {
+                sock,
+                sock_is_conn: true,
+                addr,
+            },
+            lb,
+        ))
+    }
 
-impl Leaderboard {
-    pub(super) fn read_leaderboard(&mut self, block: bool) -> Option<LeaderboardEntries> {
+    pub(super) fn read_leaderboard(&mut self, block: bool) -> Option<(u8, LeaderboardEntries)> {
         if !self.sock_is_conn {
             match self.sock.sock_finish_conn() {
                 Ok(true) => {
@@ -59,19 +77,21 @@ impl Leaderboard {
             .ok()
     }
 
-    pub const fn has_conn(&self) -> bool {
+    pub(super) const fn has_conn(&self) -> bool {
         self.sock_is_conn
     }
 
-    pub fn send_game(&mut self, name: [u8; 3], score: u8) -> Result<()> {
-        let mut packet = [0u8; 4];
+    /// Submits a game to the server, on the given leaderboard.
+    pub(super) fn send_game(&mut self, name: [u8; 3], score: u16, board: u8) -> Result<()> {
+        let mut packet = [0u8; 6];
         packet[0..3].copy_from_slice(&name);
-        packet[3] = score;
+        packet[3..5].copy_from_slice(&score.to_be_bytes());
+        packet[5] = board;
         oca_network::write_packet(&mut self.sock, 0x1, &packet)
     }
 }
 
-pub(super) fn connect_tcp(addr: SocketAddrV4) -> Result<(LeaderboardEntries, Socket)> {
+fn connect_tcp(addr: SocketAddrV4) -> Result<((u8, LeaderboardEntries), Socket)> {
     let mut conn = Socket::connect(addr, true)?;
 
     let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
@@ -82,16 +102,18 @@ pub(super) fn connect_tcp(addr: SocketAddrV4) -> Result<(LeaderboardEntries, Soc
     Ok((lb, conn))
 }
 
-fn read_leaderboard(stream: &mut Socket) -> Result<LeaderboardEntries> {
+/// Reads a leaderboard packet from the server, returning the ID of the leaderboard and its
+/// entries.
+fn read_leaderboard(stream: &mut Socket) -> Result<(u8, LeaderboardEntries)> {
     let (packet_id, packet) = oca_network::read_packet(stream)?;
     assert_eq!(packet_id, 0x0);
-    assert_eq!(packet.len(), 40);
+    assert_eq!(packet.len(), 51);
 
     let mut entries = LeaderboardEntries::default();
-    for (idx, entry) in packet.chunks(4).enumerate() {
+    for (idx, entry) in packet[1..].chunks(5).enumerate() {
         entries[idx].0 = entry[0..3].try_into().unwrap();
-        entries[idx].1 = entry[3];
+        entries[idx].1 = u16::from_be_bytes(entry[3..5].try_into().unwrap());
     }
 
-    Ok(entries)
+    Ok((packet[0], entries))
 }
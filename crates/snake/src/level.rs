@@ -0,0 +1,464 @@
+//! Levels: the layout of the canvas at the start of a game.
+//!
+//! A [`Level`] describes where the walls are, where the snake starts (and which way it faces),
+//! where the first fruits are placed, and optionally how fast the game starts. Levels are written
+//! in a small plain-text format. A handful of levels are bundled into the binary from the
+//! `levels/` directory by the build script, and more can be loaded at runtime from the directory
+//! given by the `SNAKELEVELS` environment variable (or `levels/` if unset). A runtime level
+//! replaces the bundled level with the same name, and invalid level files are skipped.
+//!
+//! # File format
+//!
+//! A level file starts with a header of `key: value` lines, followed by a blank line and then the
+//! canvas itself, as 19 rows of 28 characters (the smallest canvas, [`CanvasSize::MIN`]). Lines
+//! starting with `;` are comments and are ignored anywhere in the file. On a bigger canvas, the
+//! level is stretched to fit (see [`Level::fit`]).
+//!
+//! The header keys are:
+//! - `name` (required): the name of the level, shown to the player and stored in replays; and
+//! - `speed` (optional): the starting time between each step on normal difficulty, in
+//!   milliseconds (from 1 to 1000). Other difficulties scale this.
+//!
+//! Each character of the canvas is one of:
+//! - `.`: an empty cell;
+//! - `#`: a wall;
+//! - `*`: a fruit (there must be at least one, and they are used in reading order, as many as the
+//!   difficulty needs); or
+//! - `^`, `v`, `<` or `>`: the snake's starting position, facing up, down, left or right (there
+//!   must be exactly one).
+//!
+//! In a two-player game, the second snake starts from the opposite point of the canvas, facing
+//! the opposite way, so that cell must also be empty.
+//!
+//! ```text
+//! ; Two pillars in the middle of the canvas.
+//! name: Pillars
+//! speed: 150
+//!
+//! ............................
+//! ...
+//! ```
+
+use core::time::Duration;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use oca_io::{Error, Result};
+
+use crate::game::{CanvasSize, Coord, Direction, STARTING_STEP_TIME};
+
+/// The slowest starting speed that a level can have, in milliseconds between steps.
+const MAX_SPEED: u64 = 1000;
+
+/// The level files bundled into the binary, in file name order. The first one is always the
+/// classic, open canvas.
+const BUNDLED_LEVELS: &[&str] = include!(concat!(env!("OUT_DIR"), "/levels.rs"));
+
+/// The layout of the canvas at the start of a game.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Level {
+    /// The name of the level.
+    pub name: String,
+    /// The size of the canvas that the level fills.
+    pub size: CanvasSize,
+    /// Where the snake's head starts.
+    pub start: Coord,
+    /// The direction that the snake starts moving in.
+    pub direction: Direction,
+    /// Cells that the snake can never enter.
+    pub walls: Vec<Coord>,
+    /// The starting locations for the fruits.
+    pub fruits: Vec<Coord>,
+    /// The time between each step at the start of the game, on normal difficulty.
+    pub step_time: Duration,
+}
+
+impl Level {
+    /// Parses a level from the level file format. The level is the size of the smallest canvas.
+    pub fn parse(text: &str) -> Result<Self> {
+        const SIZE: CanvasSize = CanvasSize::MIN;
+
+        let mut lines = text.lines().filter(|line| !line.starts_with(';'));
+
+        let mut name = None;
+        let mut step_time = STARTING_STEP_TIME;
+        for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(Error::Other("invalid level header"))?;
+            match (key.trim(), value.trim()) {
+                ("name", value) if !value.is_empty() && value.len() <= 255 => {
+                    name = Some(value.to_owned());
+                }
+                ("speed", value) => {
+                    let millis = value
+                        .parse()
+                        .ok()
+                        .filter(|millis| (1..=MAX_SPEED).contains(millis))
+                        .ok_or(Error::Other("invalid level speed"))?;
+                    step_time = Duration::from_millis(millis);
+                }
+                _ => return Err(Error::Other("invalid level header")),
+            }
+        }
+
+        let mut start = None;
+        let mut walls = Vec::new();
+        let mut fruits = Vec::new();
+        let mut rows = 0;
+        for (y, line) in lines.enumerate() {
+            if y >= SIZE.h as usize || line.len() != SIZE.w as usize {
+                return Err(Error::Other("level does not match the canvas size"));
+            }
+            rows += 1;
+
+            for (x, ch) in line.bytes().enumerate() {
+                let coord = Coord {
+                    x: x as u16,
+                    y: y as u16,
+                };
+                let direction = match ch {
+                    b'.' => continue,
+                    b'#' => {
+                        walls.push(coord);
+                        continue;
+                    }
+                    b'*' => {
+                        fruits.push(coord);
+                        continue;
+                    }
+                    b'^' => Direction::Up,
+                    b'v' => Direction::Down,
+                    b'<' => Direction::Left,
+                    b'>' => Direction::Right,
+                    _ => return Err(Error::Other("invalid character in level")),
+                };
+                if start.replace((coord, direction)).is_some() {
+                    return Err(Error::Other("level has more than one starting position"));
+                }
+            }
+        }
+
+        if rows != SIZE.h {
+            return Err(Error::Other("level does not match the canvas size"));
+        }
+        let (start, direction) = start.ok_or(Error::Other("level has no starting position"))?;
+        if fruits.is_empty() {
+            return Err(Error::Other("level has no fruits"));
+        }
+
+        let level = Self {
+            name: name.ok_or(Error::Other("level has no name"))?,
+            size: SIZE,
+            start,
+            direction,
+            walls,
+            fruits,
+            step_time,
+        };
+
+        let (second, _) = level.second_start();
+        if second == start || level.walls.contains(&second) || level.fruits.contains(&second) {
+            return Err(Error::Other("level has no room for a second snake"));
+        }
+
+        Ok(level)
+    }
+
+    /// Returns where the second snake starts in a two-player game, and the direction it faces.
+    /// This is the mirror image of the first snake's start, through the centre of the canvas.
+    #[must_use]
+    pub const fn second_start(&self) -> (Coord, Direction) {
+        let coord = Coord {
+            x: self.size.w - 1 - self.start.x,
+            y: self.size.h - 1 - self.start.y,
+        };
+        (coord, self.direction.opposite())
+    }
+
+    /// Stretches the level to fill a canvas of the given size, which must be at least as big as
+    /// the level.
+    ///
+    /// Extra columns are added down the middle of the level, and extra rows across the middle.
+    /// The new cells are walls only where a wall crosses the middle of the level (that is, where
+    /// the cells either side of the middle are both walls), so walls are lengthened and gaps
+    /// between them are widened. Everything else keeps its distance from the nearest edges, which
+    /// keeps the level symmetric, so there is still room for the second snake.
+    #[must_use]
+    pub fn fit(&self, size: CanvasSize) -> Self {
+        assert!(size.w >= self.size.w && size.h >= self.size.h);
+        let extra = (size.w - self.size.w, size.h - self.size.h);
+        let stretch = |coord: Coord| Coord {
+            x: stretch(coord.x, self.size.w, extra.0),
+            y: stretch(coord.y, self.size.h, extra.1),
+        };
+
+        let walls = size
+            .coords()
+            .filter(|coord| {
+                let xs = sources(coord.x, self.size.w, extra.0);
+                let ys = sources(coord.y, self.size.h, extra.1);
+                xs.iter()
+                    .all(|&x| ys.iter().all(|&y| self.walls.contains(&Coord { x, y })))
+            })
+            .collect();
+
+        Self {
+            name: self.name.clone(),
+            size,
+            start: stretch(self.start),
+            direction: self.direction,
+            walls,
+            fruits: self.fruits.iter().map(|&coord| stretch(coord)).collect(),
+            step_time: self.step_time,
+        }
+    }
+
+    /// Returns the classic level: an open canvas with an 'X' of fruits.
+    #[must_use]
+    pub fn classic() -> Self {
+        bundled().next().unwrap()
+    }
+}
+
+/// Returns where a row or column at `idx` (of `len`) ends up when `extra` rows or columns are added
+/// across the middle.
+const fn stretch(idx: u16, len: u16, extra: u16) -> u16 {
+    if idx < len.div_ceil(2) {
+        idx
+    } else {
+        idx + extra
+    }
+}
+
+/// The opposite of [`stretch`]: returns the row or column (of `len`) that the row or column at
+/// `idx` came from. Added rows and columns come from the two either side of the middle (which
+/// are the same one when `len` is odd).
+const fn sources(idx: u16, len: u16, extra: u16) -> [u16; 2] {
+    let split = len.div_ceil(2);
+    if idx < split {
+        [idx, idx]
+    } else if idx < split + extra {
+        [(len - 1) / 2, len / 2]
+    } else {
+        [idx - extra, idx - extra]
+    }
+}
+
+/// Returns the levels that are bundled into the binary.
+pub fn bundled() -> impl Iterator<Item = Level> {
+    BUNDLED_LEVELS
+        .iter()
+        .map(|text| Level::parse(text).expect("bundled levels are valid"))
+}
+
+/// Returns every available level: the bundled levels, followed by any levels in the runtime level
+/// directory (sorted by file name).
+///
+/// A runtime level with the same name as a bundled level (ignoring case) replaces it. Level files
+/// that can't be read or aren't valid are skipped, as is a missing level directory.
+#[must_use]
+pub fn all() -> Vec<Level> {
+    let dir = env::var_os("SNAKELEVELS").map_or_else(|| PathBuf::from("levels"), Into::into);
+    all_in(&dir)
+}
+
+fn all_in(dir: &Path) -> Vec<Level> {
+    let mut levels: Vec<_> = bundled().collect();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return levels;
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        let Some(level) = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| Level::parse(&text).ok())
+        else {
+            continue;
+        };
+        match levels
+            .iter_mut()
+            .find(|old| old.name.eq_ignore_ascii_case(&level.name))
+        {
+            Some(old) => *old = level,
+            None => levels.push(level),
+        }
+    }
+
+    levels
+}
+
+/// Finds an available level by name (ignoring case).
+pub fn find(name: &str) -> Result<Level> {
+    all()
+        .into_iter()
+        .find(|level| level.name.eq_ignore_ascii_case(name))
+        .ok_or(Error::Other("no such level"))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{Level, all_in, bundled};
+    use crate::game::{CanvasSize, Coord, Direction};
+
+    fn grid(rows: &[(usize, &str)]) -> String {
+        let mut grid = String::new();
+        for y in 0..19 {
+            match rows.iter().find(|&&(row, _)| row == y) {
+                Some((_, row)) => grid.push_str(row),
+                None => grid.push_str(&".".repeat(CanvasSize::MIN.w as usize)),
+            }
+            grid.push('\n');
+        }
+        grid
+    }
+
+    #[test]
+    fn bundled_levels_are_valid() {
+        assert!(bundled().count() > 1);
+        let classic = Level::classic();
+        assert_eq!(classic.name, "Classic");
+        assert!(classic.walls.is_empty());
+        assert_eq!(classic.fruits.len(), 5);
+    }
+
+    #[test]
+    fn loads_runtime_levels() {
+        let dir = std::env::temp_dir().join(format!("snake-levels-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pillars = format!(
+            "name: pillars\nspeed: 90\n\n{}",
+            grid(&[(5, ".>..*.......................")])
+        );
+        let extra = format!(
+            "name: Extra\n\n{}",
+            grid(&[(5, ".>..*.......................")])
+        );
+        std::fs::write(dir.join("a.txt"), pillars).unwrap();
+        std::fs::write(dir.join("b.txt"), "name: Broken\n\n").unwrap();
+        std::fs::write(dir.join("c.txt"), extra).unwrap();
+
+        // The broken file is skipped, and a level with a bundled level's name replaces it.
+        let levels = all_in(&dir);
+        assert_eq!(levels.len(), bundled().count() + 1);
+        let pillars = levels.iter().find(|level| level.name == "pillars").unwrap();
+        assert_eq!(pillars.step_time, Duration::from_millis(90));
+        assert!(!levels.iter().any(|level| level.name == "Pillars"));
+        assert_eq!(levels.last().unwrap().name, "Extra");
+
+        // The bundled levels themselves aren't listed twice.
+        let bundled_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/levels");
+        assert_eq!(all_in(bundled_dir.as_ref()), bundled().collect::<Vec<_>>());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_level() {
+        let text = format!(
+            "; a comment\nname: Test\nspeed: 100\n\n{}",
+            grid(&[
+                (2, "..#.......................*."),
+                (5, ".<##........................")
+            ])
+        );
+        let level = Level::parse(&text).unwrap();
+        assert_eq!(level.name, "Test");
+        assert_eq!(level.step_time, Duration::from_millis(100));
+        assert_eq!(level.start, Coord { x: 1, y: 5 });
+        assert_eq!(level.direction, Direction::Left);
+        assert_eq!(
+            level.walls,
+            [
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 5 },
+                Coord { x: 3, y: 5 }
+            ]
+        );
+        assert_eq!(level.fruits, [Coord { x: 26, y: 2 }]);
+        assert_eq!(
+            level.second_start(),
+            (Coord { x: 26, y: 13 }, Direction::Right)
+        );
+    }
+
+    #[test]
+    fn fits_bigger_canvas() {
+        let size = CanvasSize { w: 32, h: 22 };
+        let box_level = bundled().find(|level| level.name == "Box").unwrap();
+        let level = box_level.fit(size);
+        assert_eq!(level.size, size);
+        assert_eq!(level.start, box_level.start);
+        assert_eq!(level.fruits[0], Coord { x: 22, y: 6 });
+        assert_eq!(level.fruits[4], Coord { x: 28, y: 15 });
+
+        // The walls around the edge get longer, and the gaps in the middle of each side get
+        // wider.
+        let walls = &level.walls;
+        let walls_in_row = |y| (0..size.w).filter(move |&x| walls.contains(&Coord { x, y }));
+        assert_eq!(walls_in_row(0).count(), 22);
+        assert_eq!(walls_in_row(size.h - 1).count(), 22);
+        assert!(!level.walls.contains(&Coord { x: 0, y: 10 }));
+        assert!(level.walls.contains(&Coord { x: 0, y: 5 }));
+        assert!(level.walls.contains(&Coord { x: 31, y: 16 }));
+        assert_eq!(level.walls.len(), 2 * 22 + 2 * 12);
+
+        assert_eq!(box_level.fit(CanvasSize::MIN), box_level);
+        for level in bundled() {
+            let level = level.fit(CanvasSize::MAX);
+            let (second, _) = level.second_start();
+            assert!(!level.walls.contains(&second) && !level.fruits.contains(&second));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_levels() {
+        let fruit = (0, "*...........................");
+        let start = (1, ">...........................");
+        let parse = |header: &str, rows: &[(usize, &str)]| {
+            Level::parse(&format!("{header}\n\n{}", grid(rows)))
+        };
+
+        assert!(parse("name: Test", &[fruit, start]).is_ok());
+        assert!(parse("", &[fruit, start]).is_err());
+        assert!(parse("name: Test\nspeed: fast", &[fruit, start]).is_err());
+        assert!(parse("name: Test\nspeed: 0", &[fruit, start]).is_err());
+        assert!(parse("name: Test\nspeed: 18446744073709551615", &[fruit, start]).is_err());
+        assert!(parse("name: Test\nspeed: 1000", &[fruit, start]).is_ok());
+        assert!(parse("name: Test", &[fruit]).is_err());
+        assert!(parse("name: Test", &[start]).is_err());
+        assert!(
+            parse(
+                "name: Test",
+                &[fruit, start, (2, "..^.........................")]
+            )
+            .is_err()
+        );
+        assert!(
+            parse(
+                "name: Test",
+                &[fruit, start, (2, "..x.........................")]
+            )
+            .is_err()
+        );
+        assert!(parse("name: Test", &[fruit, start, (2, "...")]).is_err());
+        assert!(
+            parse(
+                "name: Test",
+                &[fruit, start, (17, "...........................#")]
+            )
+            .is_err()
+        );
+        assert!(Level::parse("name: Test\n\n*>\n").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+//! The engine shared by the `snake` and `snake-sim` binaries.
+//!
+//! Everything here is independent of the terminal UI: the rules of the game ([`game`]), its
+//! levels and difficulty curves, the controllers that steer the snakes (including the
+//! [`autopilot`]), the replay format, and where the game keeps its files ([`state_dir`]). The
+//! binaries decide how (or whether) games are drawn.
+
+#![warn(clippy::pedantic, clippy::nursery)]
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::module_name_repetitions
+)]
+
+pub mod autopilot;
+pub mod controller;
+pub mod difficulty;
+pub mod game;
+pub mod level;
+pub mod replay;
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Returns the directory that the game keeps its state in on this machine.
+///
+/// This holds the local leaderboards, player profiles and replays: `$XDG_STATE_HOME/snake/`, or
+/// `~/.local/state/snake/`. Returns [`None`] if neither `XDG_STATE_HOME` nor `HOME` is set.
+pub fn state_dir() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".local/state")))?;
+    Some(state.join("snake"))
+}
+
+/// Writes a file in the state directory (see [`state_dir`]), creating the directory if needed.
+///
+/// The contents are written to a temporary file which then replaces the old file, so that the
+/// file is never left half-written if the game is killed (or the disk fills up) part way through.
+pub fn write_state_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
@@ -3,20 +3,41 @@
 #![allow(clippy::cast_possible_truncation, clippy::module_name_repetitions)]
 
 mod attractor;
+mod ghost;
 mod leaderboard;
+mod menu;
+mod profile;
 mod snake;
 mod ui;
 
 use core::{fmt::Write as _, time::Duration};
-use oca_io::{Result, file::File, format, timer::Instant};
+use std::env;
 
+use oca_io::{
+    Error, Result, Rng, StaticString, file::File, format, network::LeaderboardEntries,
+    timer::Instant,
+};
+
+use attractor::Attractor;
+use ghost::Ghost;
+use menu::Menu;
 use oca_term::{Color, Key, KeyEvent, Popup};
+use profile::Profiles;
 use snake::game_main;
+use snakegame::{
+    controller,
+    game::GameState,
+    level::{self, Level},
+    replay::Replay,
+};
 use ui::GameUi;
 
 const GAME_OVER_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/game-over.txt"));
 const ADD_LB_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/add-lb.txt"));
-const WELCOME_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/welcome.txt"));
+const WINNER_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/winner.txt"));
+const DRAW_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/draw.txt"));
+const GAME_OVER_PROFILE_TEXT: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/game-over-profile.txt"));
 
 fn main() {
     if let Err(err) = snake_main() {
@@ -25,52 +46,89 @@ fn main() {
 }
 
 fn snake_main() -> Result<()> {
-    let mut ui = GameUi::init()?;
+    // If `SNAKESEED` is set, then every game uses that seed, which makes games reproducible.
+    let fixed_seed = match env::var("SNAKESEED") {
+        Ok(seed) => Some(
+            seed.parse::<u64>()
+                .map_err(|_| Error::Other("invalid SNAKESEED value"))?,
+        ),
+        Err(_) => None,
+    };
+
+    // `SNAKELEVEL` chooses the level that every game is played on, by name.
+    let level = match env::var("SNAKELEVEL") {
+        Ok(name) => level::find(&name)?,
+        Err(_) => Level::classic(),
+    };
+
+    // `snake --replay <file>` plays back a recorded game instead of starting the normal game.
+    let mut args = env::args().skip(1);
+    let replay = match (args.next().as_deref(), args.next()) {
+        (None, _) => None,
+        (Some("--replay"), Some(path)) => Some(Replay::load(path)?),
+        _ => return Err(Error::Other("usage: snake [--replay <file>]")),
+    };
+
+    // A replay is watched on the canvas it was played on, and otherwise the canvas is as big as
+    // the terminal allows.
+    let mut ui = GameUi::init(replay.as_ref().map(|replay| replay.size))?;
     // Flush to make sure we don't exhaust output buffer.
     // FIXME: automatically detect a full output buffer, and automatically flush
     ui.flush()?;
 
-    loop {
-        let popup = Popup::new(WELCOME_TEXT);
-        let pos = ui.draw_centered(&popup, false)?;
-        ui.flush()?;
+    if let Some(replay) = replay {
+        let level = level::find(&replay.level)?;
+        ui.set_config(replay.config)?;
+        return watch_replay(&mut ui, &replay, &level);
+    }
 
-        if attractor::run(&mut ui)? {
+    let level = level.fit(ui.size());
+    // The menu remembers what was chosen last time, for the next game.
+    let mut menu = Menu::new();
+    let mut profiles = Profiles::open();
+    // The initials that were last entered for the leaderboard, whose profile games are recorded in.
+    let mut player = None;
+    loop {
+        // The last game is still on the canvas, so the attractor starts again from scratch.
+        let mut attractor = Attractor::new();
+        let Some((config, pos)) = attractor.run(&mut ui, &mut menu)? else {
             break;
-        }
-        ui.clear_centered(&popup, pos)?;
+        };
+
+        ui.clear_centered(&Popup::new(&menu.text()?), pos)?;
+        ui.set_config(config)?;
         ui.clear_canvas()?;
-        if let Some(lb) = ui.lb() {
+        // A notice from the last game (such as its replay not being saved) is out of date now.
+        ui.set_notice(None)?;
+        if !config.two_player
+            && let Some(lb) = ui.lb()
+        {
             lb.score = Some(0);
             ui.reset_lb(false)?;
         }
 
-        match game_main(&mut ui)? {
-            Some(score) => {
-                let needs_lb_update = if let Some(lb) = ui.lb()
-                    && lb.has_conn()
-                    && score > lb.entries[9].1.into()
-                    && score > 10
-                {
-                    do_highscore(&mut ui, score)?
+        let seed = fixed_seed.map_or_else(Rng::random_seed, Ok)?;
+        let mut controllers = controller::keyboards(config);
+        let ghost = fixed_seed.and_then(|seed| Ghost::find(seed, config, &level));
+        // Don't count the time spent on the welcome screen.
+        ui.reset_stats()?;
+        match game_main(&mut ui, seed, config, &level, &mut controllers, ghost)? {
+            Some(state) => {
+                // Two-player games don't go on the leaderboard, we just announce the winner.
+                let exit = if config.two_player {
+                    game_over(&mut ui, &state, None, Some(Duration::from_secs(10)))?
+                        == KeyEvent::Exit
                 } else {
-                    let game_over_text =
-                        GAME_OVER_TEXT.replace("000", &format!(len 3, "{score:0>3}"));
-                    let popup = Popup::new(&game_over_text).with_color(Color::Red);
-                    let pos = ui.draw_centered(&popup, true)?;
-
-                    ui.flush()?;
-                    if ui.term().wait_enter(Some(Duration::from_secs(10)))? == KeyEvent::Exit {
-                        break;
-                    }
-                    ui.clear_centered(&popup, pos)?;
-                    false
+                    single_player_over(&mut ui, &state, profiles.as_mut(), &mut player)?
                 };
+                if exit {
+                    break;
+                }
 
                 // TODO: we don't need to do this if `do_highscore` was called
                 if let Some(lb) = ui.lb() {
                     lb.score = None;
-                    ui.reset_lb(needs_lb_update)?;
+                    ui.reset_lb(false)?;
                 }
                 ui.reset_stats()?;
                 ui.clear_canvas()?;
@@ -82,7 +140,141 @@ fn snake_main() -> Result<()> {
     Ok(())
 }
 
-fn do_highscore(ui: &mut GameUi, score: usize) -> Result<bool> {
+fn watch_replay(ui: &mut GameUi, replay: &Replay, level: &Level) -> Result<()> {
+    if let Some(state) = snake::playback(ui, replay, level)? {
+        game_over(ui, &state, None, None)?;
+    }
+
+    Ok(())
+}
+
+/// How a single-player game compares with the player's profile from before it, which is shown
+/// in the "game over" popup.
+struct ProfileChange {
+    /// The player's personal best before this game.
+    best: u16,
+    /// Where the player was on the leaderboard before and after this game (see [`rank`]), if the
+    /// game was added to the leaderboard.
+    ranks: Option<(Option<usize>, Option<usize>)>,
+}
+
+/// Shows the "game over" popup for a finished game until <ENTER> is pressed (or the timeout
+/// passes). Single-player games show the score, along with how it compares with the player's
+/// profile if there is one, and two-player games show the winner.
+fn game_over(
+    ui: &mut GameUi,
+    state: &GameState,
+    profile: Option<ProfileChange>,
+    timeout: Option<Duration>,
+) -> Result<KeyEvent> {
+    let score = format!(len 3, "{:0>3}", state.score());
+    let text = if state.config().two_player {
+        state.winner().map_or_else(
+            || DRAW_TEXT.to_owned(),
+            |winner| WINNER_TEXT.replace('#', &format!(len 1, "{}", winner + 1)),
+        )
+    } else if let Some(profile) = profile {
+        let best = if state.score() > profile.best.into() {
+            format!(len 32, "\x1B[1;92mNew personal best!\x1B[0m")
+        } else {
+            format!(len 32, "Personal best: \x1B[1m{:0>3}\x1B[0m", profile.best)
+        };
+        let text = GAME_OVER_PROFILE_TEXT
+            .replace("000", &score)
+            .replace("BEST", &best);
+        match profile.ranks {
+            Some((old, new)) => {
+                let (old, new) = (rank_str(old), rank_str(new));
+                let ranks =
+                    format!(len 48, "Leaderboard: \x1B[1m{old}\x1B[22m → \x1B[1m{new}\x1B[0m");
+                text.replace("RANK", &ranks)
+            }
+            None => text.replace("\nRANK", ""),
+        }
+    } else {
+        GAME_OVER_TEXT.replace("000", &score)
+    };
+
+    let popup = Popup::new(&text).with_color(Color::Red);
+    let pos = ui.draw_centered(&popup, true)?;
+    ui.flush()?;
+    let event = ui.term().wait_enter(timeout)?;
+    ui.clear_centered(&popup, pos)?;
+    Ok(event)
+}
+
+/// Shows a rank from [`rank`] the way the leaderboard does, or dashes if there isn't one.
+fn rank_str(rank: Option<usize>) -> StaticString<3> {
+    rank.map_or_else(
+        || format!(len 3, "---"),
+        |rank| format!(len 3, "#{:0>2}", rank + 1),
+    )
+}
+
+/// Finishes a single-player game. If the game made the leaderboard, the player is asked for their
+/// initials to add it, and these become the initials of the session's `player`. The game is then
+/// recorded in the player's profile (if the initials are known), and unless it was just added to
+/// the leaderboard by a new player, the "game over" popup is shown until <ENTER> is pressed or 10
+/// seconds pass. A game that the player chose not to add to the leaderboard isn't recorded.
+///
+/// Returns `true` if the program should exit.
+fn single_player_over(
+    ui: &mut GameUi,
+    state: &GameState,
+    profiles: Option<&mut Profiles>,
+    player: &mut Option<[u8; 3]>,
+) -> Result<bool> {
+    let score = state.score();
+    // Remember the leaderboard from before this game, to work out how the player's rank changed.
+    let old_entries = ui
+        .lb()
+        .filter(|lb| lb.has_conn() && score > lb.entries[9].1.into() && score > 10)
+        .map(|lb| lb.entries);
+
+    let ranks = if let Some(old_entries) = old_entries {
+        let Some(name) = do_highscore(ui, score)? else {
+            return Ok(false);
+        };
+        *player = Some(name);
+
+        // The server decides how the game is added, so wait for the leaderboard that it sends
+        // back (local leaderboards have already been updated) before working out the new rank.
+        ui.lb().unwrap().score = None;
+        ui.reset_lb(true)?;
+        let new_entries = ui.lb().unwrap().entries;
+        Some((rank(&old_entries, name), rank(&new_entries, name)))
+    } else {
+        None
+    };
+
+    let old = match (profiles, *player) {
+        (Some(profiles), Some(name)) => {
+            let board = leaderboard::board_for(state.config()).0;
+            profiles.record(name, board, score as u16, state.snakes()[0].eaten())
+        }
+        _ => None,
+    };
+
+    // A new player has just seen their game go on the leaderboard, so there is nothing to add.
+    if old.is_none() && ranks.is_some() {
+        return Ok(false);
+    }
+    let profile = old.map(|old| ProfileChange {
+        best: old.best,
+        ranks,
+    });
+    Ok(game_over(ui, state, profile, Some(Duration::from_secs(10)))? == KeyEvent::Exit)
+}
+
+/// Returns where the player with the given initials is on a leaderboard (from 0), which is the
+/// position of their best entry, or [`None`] if they aren't on it.
+fn rank(entries: &LeaderboardEntries, name: [u8; 3]) -> Option<usize> {
+    entries.iter().position(|entry| entry.0 == name)
+}
+
+/// Asks the player for their initials, and adds their game to the leaderboard being shown.
+/// Returns the initials, or [`None`] if the player chose not to add their game (with <ESC>).
+fn do_highscore(ui: &mut GameUi, score: usize) -> Result<Option<[u8; 3]>> {
     ui.term().clear_input()?;
 
     let game_over_text = ADD_LB_TEXT.replace("000", &format!(len 3, "{score:0>3}"));
@@ -101,7 +293,8 @@ fn do_highscore(ui: &mut GameUi, score: usize) -> Result<bool> {
             .get_key_timeout(Some(next_update - Instant::now()?), |k| {
                 matches!(k, Key::Char(_) | Key::Back | Key::Enter | Key::Esc)
             })? {
-            Some(Key::Char(ch)) if cursor_pos < 3 => {
+            // Only letters and digits, which is what the leaderboard files allow.
+            Some(Key::Char(ch)) if cursor_pos < 3 && ch.is_ascii_alphanumeric() => {
                 let ch = ch.to_ascii_uppercase();
                 input[cursor_pos as usize] = ch;
                 cursor_pos += 1;
@@ -117,11 +310,11 @@ fn do_highscore(ui: &mut GameUi, score: usize) -> Result<bool> {
                 cursor_pos -= 1;
             }
             Some(Key::Enter) if cursor_pos == 3 => {
-                ui.lb().unwrap().send_game(input, score as u8)?;
+                ui.lb().unwrap().send_game(input, score as u16)?;
                 ui.update_lb(leaderboard::LeaderboardUpdate::FillPlayer(input))?;
-                break true;
+                break Some(input);
             }
-            Some(Key::Esc) if cursor_pos == 0 => break false,
+            Some(Key::Esc) if cursor_pos == 0 => break None,
             _ => (),
         }
 
@@ -0,0 +1,207 @@
+//! The menu on the welcome screen, where the player chooses how to play.
+//!
+//! The menu lists each kind of game, followed by the settings that apply to all of them (the
+//! difficulty and combo scoring). The player moves between them with <UP> and <DOWN>, changes a
+//! setting with <LEFT> and <RIGHT> (or <ENTER>), and starts the highlighted game with <ENTER>. The
+//! menu is drawn in the welcome popup, and [`crate::attractor::Attractor::run`] handles the keys.
+
+use core::fmt::Write as _;
+
+use oca_io::{Result, format};
+use oca_term::{Color, Key};
+
+use snakegame::{
+    difficulty::Difficulty,
+    game::{GameConfig, GameMode},
+};
+
+const WELCOME_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/welcome.txt"));
+
+/// The rules for the classic game, which the other games are variations of.
+const CLASSIC: GameConfig = GameConfig {
+    wrap: false,
+    two_player: false,
+    difficulty: Difficulty::Normal,
+    combo: false,
+    mode: GameMode::Classic,
+};
+
+/// The games in the menu: the name shown, the colour it's shown in, and its rules. The difficulty
+/// and combo scoring are settings, so they are filled in when the game starts.
+const GAMES: [(&str, [u8; 2], GameConfig); 6] = [
+    ("Classic", *b"37", CLASSIC),
+    (
+        "Wrap-around",
+        Color::Cyan.fg_bright(),
+        GameConfig {
+            wrap: true,
+            ..CLASSIC
+        },
+    ),
+    (
+        "Two players",
+        Color::Green.fg_bright(),
+        GameConfig {
+            two_player: true,
+            ..CLASSIC
+        },
+    ),
+    (
+        "Time attack",
+        Color::Yellow.fg_bright(),
+        GameConfig {
+            mode: GameMode::TimeAttack,
+            ..CLASSIC
+        },
+    ),
+    (
+        "Survival",
+        Color::Red.fg_bright(),
+        GameConfig {
+            mode: GameMode::Survival,
+            ..CLASSIC
+        },
+    ),
+    (
+        "Portals",
+        Color::Blue.fg_bright(),
+        GameConfig {
+            mode: GameMode::Portals,
+            ..CLASSIC
+        },
+    ),
+];
+
+/// The row of the difficulty setting, which comes straight after the games.
+const DIFFICULTY_ROW: usize = GAMES.len();
+/// The row of the combo scoring setting.
+const COMBO_ROW: usize = GAMES.len() + 1;
+/// The number of rows in the menu that can be highlighted.
+const ROWS: usize = GAMES.len() + 2;
+
+/// The welcome screen's menu, and what has been chosen in it so far.
+#[derive(Debug, Default)]
+pub struct Menu {
+    /// The highlighted row: one of [`GAMES`], or a setting.
+    selected: usize,
+    /// The game that was highlighted last, which is the one shown while a setting is highlighted.
+    game: usize,
+    difficulty: Difficulty,
+    combo: bool,
+}
+
+impl Menu {
+    /// Creates the menu, with the classic game highlighted and the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the rules for the game that is (or was last) highlighted, with the chosen settings.
+    pub const fn config(&self) -> GameConfig {
+        GameConfig {
+            difficulty: self.difficulty,
+            combo: self.combo,
+            ..GAMES[self.game].2
+        }
+    }
+
+    /// Handles a key press. Returns `true` if the highlighted game should be started, and
+    /// otherwise the menu (and [`Self::config`]) may have changed, so it should be redrawn.
+    pub const fn press(&mut self, key: Key) -> bool {
+        match (key, self.selected) {
+            (Key::Enter, row) if row < GAMES.len() => return true,
+            (Key::Up, _) => self.select((self.selected + ROWS - 1) % ROWS),
+            (Key::Down, _) => self.select((self.selected + 1) % ROWS),
+            (Key::Left, DIFFICULTY_ROW) => self.difficulty = self.difficulty.prev(),
+            (Key::Right | Key::Enter, DIFFICULTY_ROW) => self.difficulty = self.difficulty.next(),
+            (Key::Left | Key::Right | Key::Enter, COMBO_ROW) => self.combo = !self.combo,
+            _ => (),
+        }
+        false
+    }
+
+    const fn select(&mut self, row: usize) {
+        self.selected = row;
+        if row < GAMES.len() {
+            self.game = row;
+        }
+    }
+
+    /// Returns the text of the welcome popup, with this menu in it. Every row is padded to the
+    /// same width, so the popup is always the same size.
+    pub fn text(&self) -> Result<String> {
+        let mut menu = String::new();
+        for (row, (name, color, _)) in GAMES.iter().enumerate() {
+            let color = Color::to_str(color);
+            if row == self.selected {
+                writeln!(menu, "\x1B[1;{color}m> {name:<22}\x1B[0m")?;
+            } else {
+                writeln!(menu, "\x1B[2;{color}m  {name:<22}\x1B[0m")?;
+            }
+        }
+        menu.push('\n');
+
+        let settings = [
+            ("Difficulty", self.difficulty.name()),
+            ("Combo scoring", if self.combo { "ON" } else { "OFF" }),
+        ];
+        for (idx, (name, value)) in settings.into_iter().enumerate() {
+            let value = format!(len 16, "\x1B[22;1m{value:>6}");
+            if DIFFICULTY_ROW + idx == self.selected {
+                write!(menu, "\x1B[1;37m> {name:<16}{value}\x1B[0m")?;
+            } else {
+                write!(menu, "\x1B[2;37m  {name:<16}{value}\x1B[0m")?;
+            }
+            if idx == 0 {
+                menu.push('\n');
+            }
+        }
+
+        Ok(WELCOME_TEXT.replace("MENU", &menu))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oca_term::{Key, ansi_str_len};
+
+    use super::Menu;
+    use snakegame::{difficulty::Difficulty, game::GameMode};
+
+    #[test]
+    fn chooses_game() {
+        let mut menu = Menu::new();
+        assert!(!menu.press(Key::Down));
+        assert!(!menu.press(Key::Down));
+        assert!(menu.config().two_player);
+        assert!(menu.press(Key::Enter));
+
+        // Going up from the top wraps around to the settings, which don't start a game.
+        let mut menu = Menu::new();
+        assert!(!menu.press(Key::Up));
+        assert!(!menu.press(Key::Enter));
+        assert!(menu.config().combo);
+        assert!(!menu.press(Key::Up));
+        assert!(!menu.press(Key::Left));
+        assert_eq!(menu.config().difficulty, Difficulty::Easy);
+        assert_eq!(menu.config().mode, GameMode::Classic);
+    }
+
+    #[test]
+    fn rows_line_up() {
+        let mut menu = Menu::new();
+        for _ in 0..8 {
+            let text = menu.text().unwrap();
+            assert_eq!(text.lines().count(), 13);
+            for line in text
+                .lines()
+                .skip(2)
+                .take(10)
+                .filter(|line| !line.is_empty())
+            {
+                assert_eq!(ansi_str_len(line), 24, "{line:?}");
+            }
+            menu.press(Key::Down);
+        }
+    }
+}
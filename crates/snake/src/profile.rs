@@ -0,0 +1,177 @@
+//! Player profiles, keyed by the initials that players enter for the leaderboard.
+//!
+//! The player is whoever last entered their initials to add a game to a leaderboard. From then
+//! on, each single-player game updates their [`Profile`] for that game's leaderboard, whether it
+//! makes the leaderboard or not, until different initials are entered. Games played before any
+//! initials have been entered (and games that the player chose not to add to the leaderboard)
+//! aren't recorded, because there is no telling whose they are. Each leaderboard has its own
+//! profiles (see [`crate::leaderboard::board_for`]), so that a player's best score is only
+//! compared with games played by the same rules.
+//!
+//! The profiles are kept in the `profiles` file in the game's state directory (see
+//! [`snakegame::state_dir`]), so they belong to this machine, whichever leaderboard server is
+//! used. If the file is invalid, the profiles start again from empty, and if it can't be saved,
+//! the game carries on without it.
+//!
+//! # File format
+//!
+//! All integers are big-endian, like the leaderboard files.
+//!
+//! | Size | Contents                                   |
+//! |------|--------------------------------------------|
+//! | 4    | Magic bytes, `SPF2`                        |
+//! | 22*n | Profiles                                   |
+//!
+//! Each profile is the player's initials (3 bytes), the leaderboard (1 byte), the number of games
+//! played (4 bytes), their best score (2 bytes), the number of fruits eaten (4 bytes), and when
+//! they last played (8 bytes, seconds since the Unix epoch).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use oca_io::{Error, Result};
+
+/// Profile files start with these bytes.
+const FILE_MAGIC: &[u8; 4] = b"SPF2";
+
+/// The size of each profile in the file.
+const PROFILE_LEN: usize = 22;
+
+/// What is known about a player from the games they have played for one leaderboard.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Profile {
+    /// The number of games played.
+    pub games: u32,
+    /// The highest score of any of those games.
+    pub best: u16,
+    /// The total number of fruits eaten in those games.
+    pub fruits: u32,
+    /// When the last game was played, in seconds since the Unix epoch.
+    pub last_played: u64,
+}
+
+/// Every player's profiles, keyed by their initials and the leaderboard, and the file they are
+/// kept in.
+pub struct Profiles {
+    path: PathBuf,
+    profiles: BTreeMap<([u8; 3], u8), Profile>,
+}
+
+impl Profiles {
+    /// Reads the profiles file, or returns [`None`] if there is nowhere to keep it. A missing,
+    /// unreadable or invalid file just means that there are no profiles yet.
+    pub fn open() -> Option<Self> {
+        let path = snakegame::state_dir()?.join("profiles");
+        Some(Self::load(path))
+    }
+
+    fn load(path: PathBuf) -> Self {
+        let profiles = fs::read(&path)
+            .ok()
+            .and_then(|bytes| from_bytes(&bytes).ok())
+            .unwrap_or_default();
+        Self { path, profiles }
+    }
+
+    /// Adds a game to the player's profile for the game's leaderboard (creating it if they are new
+    /// to it), and saves the profiles. Returns the profile from before this game, or [`None`] if
+    /// there wasn't one.
+    pub fn record(
+        &mut self,
+        name: [u8; 3],
+        board: u8,
+        score: u16,
+        fruits: usize,
+    ) -> Option<Profile> {
+        let last_played = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let profile = self.profiles.entry((name, board)).or_default();
+        let old = (profile.games > 0).then_some(*profile);
+        profile.games += 1;
+        profile.best = profile.best.max(score);
+        profile.fruits += fruits as u32;
+        profile.last_played = last_played;
+
+        // If the file can't be saved, the profiles are still kept until the program exits.
+        let _ = snakegame::write_state_file(&self.path, &to_bytes(&self.profiles));
+        old
+    }
+}
+
+fn from_bytes(bytes: &[u8]) -> Result<BTreeMap<([u8; 3], u8), Profile>> {
+    let profiles = bytes
+        .strip_prefix(FILE_MAGIC)
+        .filter(|profiles| profiles.len().is_multiple_of(PROFILE_LEN))
+        .ok_or(Error::Other("invalid profiles file"))?;
+
+    Ok(profiles
+        .chunks(PROFILE_LEN)
+        .map(|bytes| {
+            let name = bytes[0..3].try_into().unwrap();
+            let profile = Profile {
+                games: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+                best: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+                fruits: u32::from_be_bytes(bytes[10..14].try_into().unwrap()),
+                last_played: u64::from_be_bytes(bytes[14..22].try_into().unwrap()),
+            };
+            ((name, bytes[3]), profile)
+        })
+        .collect())
+}
+
+fn to_bytes(profiles: &BTreeMap<([u8; 3], u8), Profile>) -> Vec<u8> {
+    let mut bytes = FILE_MAGIC.to_vec();
+    for ((name, board), profile) in profiles {
+        bytes.extend_from_slice(name);
+        bytes.push(*board);
+        bytes.extend_from_slice(&profile.games.to_be_bytes());
+        bytes.extend_from_slice(&profile.best.to_be_bytes());
+        bytes.extend_from_slice(&profile.fruits.to_be_bytes());
+        bytes.extend_from_slice(&profile.last_played.to_be_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{Profile, Profiles};
+
+    #[test]
+    fn records_games() {
+        let path = env::temp_dir().join(format!("snake-profiles-{}", process::id()));
+        let mut profiles = Profiles::load(path.clone());
+        assert_eq!(profiles.record(*b"AMY", 0, 40, 12), None);
+        assert_eq!(profiles.record(*b"BOB", 0, 90, 30), None);
+        // Each leaderboard has its own profile.
+        assert_eq!(profiles.record(*b"AMY", 5, 70, 20), None);
+
+        // The profiles are saved after every game.
+        let mut profiles = Profiles::load(path.clone());
+        let old = profiles.record(*b"AMY", 0, 25, 8).unwrap();
+        assert_eq!((old.games, old.best, old.fruits), (1, 40, 12));
+        assert!(old.last_played > 0);
+
+        let amy = Profiles::load(path.clone()).profiles[&(*b"AMY", 0)];
+        assert_eq!(
+            amy,
+            Profile {
+                games: 2,
+                best: 40,
+                fruits: 20,
+                ..amy
+            }
+        );
+
+        // An invalid file is the same as no file.
+        fs::write(&path, b"SPF2AMY").unwrap();
+        assert!(Profiles::load(path.clone()).profiles.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
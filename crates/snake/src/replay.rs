@@ -0,0 +1,427 @@
+//! Recording of games, for playing them back later.
+//!
+//! Because the game is completely deterministic (see [`GameState`]), a game can be reproduced
+//! exactly from its seed and the direction changes that the player made. A [`Replay`] stores only
+//! this information, which keeps replay files tiny.
+//!
+//! [`GameState`]: crate::game::GameState
+//!
+//! Replays are saved into the directory given by the `SNAKEREPLAYS` environment variable (or
+//! `replays/` in the game's state directory if unset; see [`crate::state_dir`]), and can be
+//! watched with `snake --replay <file>`. Each file is named after when the game finished, its
+//! score and its seed (`<time>-<score>-<seed>.snr`), so that the replays with a given seed can be
+//! found without reading every file.
+//!
+//! # File format
+//!
+//! All integers are little-endian.
+//!
+//! | Size | Contents                                       |
+//! |------|------------------------------------------------|
+//! | 4    | Magic bytes, `SNKR`                            |
+//! | 1    | Format version, currently `7`                  |
+//! | 1    | Game options (see below)                       |
+//! | 2    | Canvas width and height, one byte each         |
+//! | 8    | Seed                                           |
+//! | 4    | Number of ticks                                |
+//! | 2    | Final score                                    |
+//! | 4    | Number of direction changes                    |
+//! | 1    | Length of the level name                       |
+//! | m    | Level name, as UTF-8                           |
+//! | 5*n  | Direction changes (see below)                  |
+//!
+//! The game options byte has bit 0 set for wrap-around and bit 1 set for two players, holds the
+//! difficulty (easy, normal, hard, insane) in bits 2 and 3, has bit 4 set for combo scoring, and
+//! holds the mode (classic, time attack, survival, portals) in bits 5 and 6.
+//!
+//! Each direction change is the tick that it happened on (4 bytes) and a direction byte, which
+//! holds the direction (up, down, right, left) in the lowest two bits, and the snake that turned in
+//! the bits above that.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use oca_io::{Error, Result};
+
+use crate::{
+    controller::Scripted,
+    difficulty::Difficulty,
+    game::{CanvasSize, Direction, GameConfig, GameMode},
+    level::Level,
+};
+
+const MAGIC: &[u8; 4] = b"SNKR";
+const VERSION: u8 = 7;
+const HEADER_LEN: usize = 26;
+
+/// A recording of a single game.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Replay {
+    /// The seed that the game was started with.
+    pub seed: u64,
+    /// The rules that the game was played with.
+    pub config: GameConfig,
+    /// The name of the level that the game was played on.
+    pub level: String,
+    /// The size of the canvas that the game was played on.
+    pub size: CanvasSize,
+    /// The number of times that the game was stepped.
+    pub ticks: u32,
+    /// Every direction change, along with the tick that it happened on and the snake that
+    /// turned.
+    pub turns: Vec<(u32, usize, Direction)>,
+    /// The final score of the game.
+    pub score: u16,
+}
+
+impl Replay {
+    /// Starts a new, empty recording of a game with the given seed, rules and level.
+    #[must_use]
+    pub fn new(seed: u64, config: GameConfig, level: &Level) -> Self {
+        Self {
+            seed,
+            config,
+            level: level.name.clone(),
+            size: level.size,
+            ticks: 0,
+            turns: Vec::new(),
+            score: 0,
+        }
+    }
+
+    /// Records the inputs given to [`GameState::step`] for the current tick.
+    ///
+    /// [`GameState::step`]: crate::game::GameState::step
+    pub fn record(&mut self, inputs: &[Option<Direction>]) {
+        for (snake, &input) in inputs.iter().enumerate() {
+            if let Some(dir) = input {
+                self.turns.push((self.ticks, snake, dir));
+            }
+        }
+        self.ticks += 1;
+    }
+
+    /// Records the final score of the game.
+    pub fn finish(&mut self, score: usize) {
+        self.score = score.try_into().unwrap_or(u16::MAX);
+    }
+
+    /// Creates a [`Controller`](crate::controller::Controller) which steers the given snake exactly
+    /// as it was steered in this game.
+    #[must_use]
+    pub fn controller(&self, snake: usize) -> Scripted {
+        Scripted::new(
+            self.turns
+                .iter()
+                .filter(|&&(_, s, _)| s == snake)
+                .map(|&(tick, _, dir)| (tick, dir))
+                .collect(),
+        )
+    }
+
+    /// Serializes the replay into the replay file format.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + 1 + self.level.len() + self.turns.len() * 5);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(
+            u8::from(self.config.wrap)
+                | (u8::from(self.config.two_player) << 1)
+                | (self.config.difficulty.to_byte() << 2)
+                | (u8::from(self.config.combo) << 4)
+                | (self.config.mode.to_byte() << 5),
+        );
+        // Canvas sizes are limited to `CanvasSize::MAX`, which fits in a byte.
+        bytes.push(self.size.w as u8);
+        bytes.push(self.size.h as u8);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.ticks.to_le_bytes());
+        bytes.extend_from_slice(&self.score.to_le_bytes());
+        bytes.extend_from_slice(&(self.turns.len() as u32).to_le_bytes());
+        // Level names are limited to 255 bytes when they are parsed.
+        bytes.push(self.level.len() as u8);
+        bytes.extend_from_slice(self.level.as_bytes());
+        for &(tick, snake, dir) in &self.turns {
+            bytes.extend_from_slice(&tick.to_le_bytes());
+            bytes.push(dir_to_byte(dir) | ((snake as u8) << 2));
+        }
+        bytes
+    }
+
+    /// Parses a replay from the replay file format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        const INVALID: Error = Error::Other("invalid replay file");
+
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC || bytes[4] != VERSION {
+            return Err(INVALID);
+        }
+
+        let config = GameConfig {
+            wrap: bytes[5] & 0b1 != 0,
+            two_player: bytes[5] & 0b10 != 0,
+            difficulty: Difficulty::from_byte((bytes[5] >> 2) & 0b11).unwrap(),
+            combo: bytes[5] & 0b1_0000 != 0,
+            mode: GameMode::from_byte((bytes[5] >> 5) & 0b11).ok_or(INVALID)?,
+        };
+        let size = CanvasSize {
+            w: bytes[6].into(),
+            h: bytes[7].into(),
+        };
+        if size.clamp() != Some(size) {
+            return Err(INVALID);
+        }
+        let seed = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let ticks = u32::from_le_bytes(bytes[16..20].try_into().unwrap());
+        let score = u16::from_le_bytes(bytes[20..22].try_into().unwrap());
+        let count = u32::from_le_bytes(bytes[22..26].try_into().unwrap()) as usize;
+
+        let level_len = usize::from(*bytes.get(HEADER_LEN).ok_or(INVALID)?);
+        let level = bytes
+            .get(HEADER_LEN + 1..HEADER_LEN + 1 + level_len)
+            .and_then(|name| str::from_utf8(name).ok())
+            .ok_or(INVALID)?
+            .to_owned();
+
+        let body = &bytes[HEADER_LEN + 1 + level_len..];
+        if body.len() != count * 5 {
+            return Err(INVALID);
+        }
+
+        let mut turns = Vec::with_capacity(count);
+        for turn in body.chunks(5) {
+            let tick = u32::from_le_bytes(turn[0..4].try_into().unwrap());
+            let dir = byte_to_dir(turn[4] & 0b11).ok_or(INVALID)?;
+            let snake = usize::from(turn[4] >> 2);
+            if snake >= config.players() {
+                return Err(INVALID);
+            }
+            turns.push((tick, snake, dir));
+        }
+
+        Ok(Self {
+            seed,
+            config,
+            level,
+            size,
+            ticks,
+            turns,
+            score,
+        })
+    }
+
+    /// Loads a replay from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|_| Error::Other("failed to read replay file"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Saves the replay into the replay directory, returning the path of the new file.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let dir = dir().ok_or(io::ErrorKind::NotFound)?;
+        fs::create_dir_all(&dir)?;
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir.join(format!("{time}-{:0>3}-{}.snr", self.score, self.seed));
+        fs::write(&path, self.to_bytes())?;
+        Ok(path)
+    }
+
+    /// Finds the saved replay with the highest score, out of those with the given seed, rules and
+    /// level (which must be the same size). Only the files named with that seed are read, and
+    /// those that aren't valid replays are skipped.
+    #[must_use]
+    pub fn best(seed: u64, config: GameConfig, level: &Level) -> Option<Self> {
+        Self::best_in(&dir()?, seed, config, level)
+    }
+
+    fn best_in(dir: &Path, seed: u64, config: GameConfig, level: &Level) -> Option<Self> {
+        let suffix = format!("-{seed}.snr");
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_name()?.to_str()?;
+                name.ends_with(&suffix).then(|| Self::load(&path).ok())?
+            })
+            .filter(|replay| {
+                replay.seed == seed
+                    && replay.config == config
+                    && replay.level == level.name
+                    && replay.size == level.size
+            })
+            .max_by_key(|replay| replay.score)
+    }
+}
+
+/// Returns the directory that replays are saved into, or [`None`] if there is nowhere to save
+/// them.
+fn dir() -> Option<PathBuf> {
+    env::var_os("SNAKEREPLAYS")
+        .map(PathBuf::from)
+        .or_else(|| Some(crate::state_dir()?.join("replays")))
+}
+
+const fn dir_to_byte(dir: Direction) -> u8 {
+    match dir {
+        Direction::Up => 0,
+        Direction::Down => 1,
+        Direction::Right => 2,
+        Direction::Left => 3,
+    }
+}
+
+const fn byte_to_dir(byte: u8) -> Option<Direction> {
+    Some(match byte {
+        0 => Direction::Up,
+        1 => Direction::Down,
+        2 => Direction::Right,
+        3 => Direction::Left,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Replay;
+    use crate::{
+        controller::Controller as _,
+        difficulty::Difficulty,
+        game::{CanvasSize, Direction, GameConfig, GameMode, GameState},
+        level::{Level, bundled},
+    };
+
+    fn sample() -> Replay {
+        let config = GameConfig {
+            wrap: true,
+            two_player: true,
+            difficulty: Difficulty::Insane,
+            combo: true,
+            mode: GameMode::Classic,
+        };
+        let level = bundled().find(|level| level.name == "Pillars").unwrap();
+        let size = CanvasSize { w: 30, h: 20 };
+        let mut replay = Replay::new(0xDEAD_BEEF, config, &level.fit(size));
+        for inputs in [
+            [None, None],
+            [Some(Direction::Up), None],
+            [None, None],
+            [None, Some(Direction::Down)],
+            [Some(Direction::Left), Some(Direction::Up)],
+            [None, None],
+        ] {
+            replay.record(&inputs);
+        }
+        replay.finish(12);
+        replay
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = sample();
+        let bytes = replay.to_bytes();
+        assert_eq!(bytes.len(), 26 + 1 + 7 + 4 * 5);
+        let parsed = Replay::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            parsed.turns[2..],
+            [(4, 0, Direction::Left), (4, 1, Direction::Up)]
+        );
+        assert_eq!(parsed, replay);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut bytes = sample().to_bytes();
+        assert!(Replay::from_bytes(&bytes[..10]).is_err());
+        bytes.pop();
+        assert!(Replay::from_bytes(&bytes).is_err());
+        bytes[0] = b'X';
+        assert!(Replay::from_bytes(&bytes).is_err());
+
+        // The canvas must be a size that the game supports.
+        let mut bytes = sample().to_bytes();
+        bytes[6] = 27;
+        assert!(Replay::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn finds_best() {
+        let dir = std::env::temp_dir().join(format!("snake-replays-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Only replays of the same game count, however well the others went.
+        let mut other_seed = sample();
+        other_seed.seed += 1;
+        other_seed.finish(99);
+        let mut best = sample();
+        best.finish(30);
+        let seed = sample().seed;
+        for (name, replay) in [
+            (format!("1-012-{seed}.snr"), sample()),
+            (format!("2-030-{seed}.snr"), best),
+            (format!("3-099-{}.snr", seed + 1), other_seed),
+        ] {
+            fs::write(dir.join(name), replay.to_bytes()).unwrap();
+        }
+        fs::write(dir.join(format!("4-000-{seed}.snr")), b"not a replay").unwrap();
+
+        // Files that aren't named with the seed aren't even read.
+        let mut misnamed = sample();
+        misnamed.finish(50);
+        fs::write(dir.join("5-050.snr"), misnamed.to_bytes()).unwrap();
+
+        let replay = sample();
+        let level = bundled().find(|level| level.name == "Pillars").unwrap();
+        let found = Replay::best_in(&dir, replay.seed, replay.config, &level.fit(replay.size));
+        assert_eq!(found.map(|replay| replay.score), Some(30));
+        assert!(Replay::best_in(&dir, replay.seed, GameConfig::default(), &level).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replays_game() {
+        let inputs = [
+            (Direction::Right, 18),
+            (Direction::Up, 3),
+            (Direction::Right, 6),
+            (Direction::Down, 6),
+            (Direction::Left, 6),
+            (Direction::Up, 20),
+        ];
+
+        // Play and record a game...
+        let level = Level::classic();
+        let mut game = GameState::new(7, GameConfig::default(), &level);
+        let mut replay = Replay::new(7, GameConfig::default(), &level);
+        for (dir, n) in inputs {
+            for i in 0..n {
+                let input = (i == 0 && dir != game.snakes()[0].direction()).then_some(dir);
+                replay.record(&[input]);
+                game.step(&[input]);
+            }
+        }
+        replay.finish(game.score());
+
+        // ...and then check that playing it back gives the same result.
+        let mut state = GameState::new(replay.seed, replay.config, &level);
+        let mut controller = replay.controller(0);
+        for _ in 0..replay.ticks {
+            let input = controller.next(&state, 0);
+            state.step(&[input]);
+        }
+        assert!(state.is_finished());
+        assert_eq!(state.snakes()[0].head(), game.snakes()[0].head());
+        assert_eq!(state.score(), usize::from(replay.score));
+        assert!(state.score() > 0);
+    }
+}
@@ -0,0 +1,419 @@
+//! Headless batch simulator for Snake.
+//!
+//! `snake-sim` plays many games with no terminal, each steered by a bot [`Controller`], and
+//! reports how they went: the distribution of scores, how long the games lasted, what the snake
+//! died on, and how often it won. This makes it easy to see the effect of a change to the
+//! difficulty curves or fruit rules before it goes anywhere near a real player.
+//!
+//! ```text
+//! snake-sim [--bot <name>] [--seeds <first>..<last>] [--level <name>] [--size <w>x<h>]
+//!           [--difficulty <name>] [--wrap] [--combo] [--time-attack] [--survival]
+//!           [--portals] [--csv]
+//! ```
+//!
+//! Games are played with every seed in the (half-open) range, 0..1000 by default, spread across
+//! every CPU. The canvas is the smallest size unless `--size` is given. With `--csv`, a line is
+//! printed for each game instead of the summary.
+
+#![warn(clippy::pedantic, clippy::nursery)]
+#![allow(clippy::cast_possible_truncation, clippy::module_name_repetitions)]
+
+use core::{ops::Range, time::Duration};
+use std::{env, process, thread};
+
+use oca_io::{Error, Result};
+use snakegame::{
+    controller::{self, Autopilot, Controller},
+    difficulty::Difficulty,
+    game::{CanvasSize, DeathCause, GameConfig, GameEvent, GameMode, GameState},
+    level::{self, Level},
+};
+
+const USAGE: &str = "usage: snake-sim [--bot <name>] [--seeds <first>..<last>] [--level <name>] \
+                     [--size <w>x<h>] [--difficulty <name>] [--wrap] [--combo] [--time-attack] \
+                     [--survival] [--portals] [--csv]";
+
+/// Games that go on for longer than this are given up on, in case a bot goes round in circles.
+const MAX_TICKS: u32 = 100_000;
+
+/// The number of bars in the score histogram.
+const HISTOGRAM_BARS: usize = 10;
+
+/// The length of the longest bar in the score histogram.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Creates a controller for a bot.
+type NewBot = fn() -> Box<dyn Controller>;
+
+/// Every bot that can be chosen with `--bot`, by name. The first is the default.
+const BOTS: &[(&str, NewBot)] = &[("autopilot", || Box::new(Autopilot))];
+
+fn main() {
+    if let Err(err) = sim_main() {
+        eprintln!("\x1B[91;1merror\x1B[0m: {err:?}");
+        process::exit(1);
+    }
+}
+
+fn sim_main() -> Result<()> {
+    let options = Options::parse(env::args().skip(1))?;
+
+    // Play the games on every CPU, each thread taking every nth seed.
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    let mut results: Vec<GameResult> = thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(threads);
+        for first in 0..threads {
+            let options = &options;
+            handles.push(scope.spawn(move || {
+                options
+                    .seeds
+                    .clone()
+                    .skip(first)
+                    .step_by(threads)
+                    .map(|seed| simulate(seed, options))
+                    .collect::<Vec<_>>()
+            }));
+        }
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|result| result.seed);
+
+    if options.csv {
+        print_csv(&results);
+    } else {
+        print_summary(&options, &results);
+    }
+    Ok(())
+}
+
+/// The command-line options.
+struct Options {
+    /// The name of the bot that steers the snake.
+    bot: &'static str,
+    /// Creates a controller for the chosen bot.
+    new_bot: NewBot,
+    /// Every seed that a game is played with.
+    seeds: Range<u64>,
+    /// The level that every game is played on, already stretched to the canvas size.
+    level: Level,
+    /// The rules that every game is played with.
+    config: GameConfig,
+    /// Whether to print a line for each game as CSV, rather than the summary.
+    csv: bool,
+}
+
+impl Options {
+    /// Parses the command-line arguments (without the program name).
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        const INVALID: Error = Error::Other(USAGE);
+
+        let (bot, new_bot) = BOTS[0];
+        let mut size = CanvasSize::MIN;
+        let mut options = Self {
+            bot,
+            new_bot,
+            seeds: 0..1000,
+            level: Level::classic(),
+            config: GameConfig::default(),
+            csv: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bot" => {
+                    let name = args.next().ok_or(INVALID)?;
+                    (options.bot, options.new_bot) = *BOTS
+                        .iter()
+                        .find(|(bot, _)| *bot == name)
+                        .ok_or(Error::Other("no such bot"))?;
+                }
+                "--seeds" => {
+                    let range = args.next().ok_or(INVALID)?;
+                    let (first, last) = range.split_once("..").ok_or(INVALID)?;
+                    options.seeds =
+                        first.parse().map_err(|_| INVALID)?..last.parse().map_err(|_| INVALID)?;
+                }
+                "--level" => options.level = level::find(&args.next().ok_or(INVALID)?)?,
+                "--size" => {
+                    let arg = args.next().ok_or(INVALID)?;
+                    let (w, h) = arg.split_once('x').ok_or(INVALID)?;
+                    size = CanvasSize {
+                        w: w.parse().map_err(|_| INVALID)?,
+                        h: h.parse().map_err(|_| INVALID)?,
+                    };
+                    if size.clamp() != Some(size) {
+                        return Err(Error::Other("unsupported canvas size"));
+                    }
+                }
+                "--difficulty" => {
+                    let name = args.next().ok_or(INVALID)?;
+                    options.config.difficulty = Difficulty::ALL
+                        .into_iter()
+                        .find(|difficulty| difficulty.name().eq_ignore_ascii_case(&name))
+                        .ok_or(Error::Other("no such difficulty"))?;
+                }
+                "--wrap" => options.config.wrap = true,
+                "--combo" => options.config.combo = true,
+                "--time-attack" => options.config.mode = GameMode::TimeAttack,
+                "--survival" => options.config.mode = GameMode::Survival,
+                "--portals" => options.config.mode = GameMode::Portals,
+                "--csv" => options.csv = true,
+                _ => return Err(INVALID),
+            }
+        }
+
+        if options.seeds.is_empty() {
+            return Err(Error::Other("no seeds to play"));
+        }
+        options.level = options.level.fit(size);
+        Ok(options)
+    }
+}
+
+/// How a game ended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Outcome {
+    /// The canvas was filled.
+    Won,
+    /// The snake died.
+    Died(DeathCause),
+    /// The time ran out in time attack.
+    TimeUp,
+    /// The game went on for [`MAX_TICKS`] without finishing.
+    TimedOut,
+}
+
+impl Outcome {
+    /// Every outcome, in the order that they are reported.
+    const ALL: [Self; 6] = [
+        Self::Won,
+        Self::Died(DeathCause::Wall),
+        Self::Died(DeathCause::Tail),
+        Self::Died(DeathCause::Snake),
+        Self::TimeUp,
+        Self::TimedOut,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Won => "won",
+            Self::Died(DeathCause::Wall) => "wall",
+            Self::Died(DeathCause::Tail) => "tail",
+            Self::Died(DeathCause::Snake) => "snake",
+            Self::TimeUp => "time up",
+            Self::TimedOut => "timed out",
+        }
+    }
+}
+
+/// The result of a single simulated game.
+#[derive(Debug)]
+struct GameResult {
+    seed: u64,
+    score: usize,
+    /// The number of steps that the game lasted.
+    ticks: u32,
+    /// How long the game would have taken to play in real time.
+    time: Duration,
+    outcome: Outcome,
+}
+
+/// Plays a single game with the given seed, letting the chosen bot steer.
+fn simulate(seed: u64, options: &Options) -> GameResult {
+    let mut state = GameState::new(seed, options.config, &options.level);
+    let mut controllers = [(options.new_bot)()];
+    let mut time = Duration::ZERO;
+    let mut outcome = Outcome::TimedOut;
+
+    while !state.is_finished() && state.ticks() < MAX_TICKS {
+        time += state.step_time();
+        let inputs = controller::inputs(&mut controllers, &state);
+        for event in state.step(&inputs) {
+            match event {
+                GameEvent::Died { cause, .. } => outcome = Outcome::Died(cause),
+                GameEvent::Won => outcome = Outcome::Won,
+                GameEvent::TimeUp => outcome = Outcome::TimeUp,
+                _ => (),
+            }
+        }
+    }
+
+    GameResult {
+        seed,
+        score: state.score(),
+        ticks: state.ticks(),
+        time,
+        outcome,
+    }
+}
+
+/// Prints a line for each game, as CSV with a header.
+fn print_csv(results: &[GameResult]) {
+    println!("seed,score,ticks,seconds,outcome");
+    for result in results {
+        println!(
+            "{},{},{},{:.3},{}",
+            result.seed,
+            result.score,
+            result.ticks,
+            result.time.as_secs_f64(),
+            result.outcome.name()
+        );
+    }
+}
+
+/// Prints a summary of every game: the score distribution, game length and outcomes.
+#[allow(clippy::cast_precision_loss)]
+fn print_summary(options: &Options, results: &[GameResult]) {
+    let games = results.len();
+    let percent = |n: usize| n as f64 * 100.0 / games as f64;
+
+    let mut scores: Vec<_> = results.iter().map(|result| result.score).collect();
+    scores.sort_unstable();
+    let total_ticks: u64 = results.iter().map(|result| u64::from(result.ticks)).sum();
+    let total_time: Duration = results.iter().map(|result| result.time).sum();
+
+    println!(
+        "{games} games (seeds {}..{}), {} bot, {} level ({}x{}), {} difficulty{}{}{}",
+        options.seeds.start,
+        options.seeds.end,
+        options.bot,
+        options.level.name,
+        options.level.size.w,
+        options.level.size.h,
+        options.config.difficulty.name().to_lowercase(),
+        if options.config.wrap { ", wrap" } else { "" },
+        if options.config.combo { ", combo" } else { "" },
+        match options.config.mode {
+            GameMode::Classic => "",
+            GameMode::TimeAttack => ", time attack",
+            GameMode::Survival => ", survival",
+            GameMode::Portals => ", portals",
+        },
+    );
+    println!();
+
+    println!(
+        "Score:    min {}, median {}, mean {:.1}, max {}",
+        scores[0],
+        scores[games / 2],
+        scores.iter().sum::<usize>() as f64 / games as f64,
+        scores[games - 1],
+    );
+    println!(
+        "Length:   mean {:.0} ticks, {:.1} seconds",
+        total_ticks as f64 / games as f64,
+        total_time.as_secs_f64() / games as f64,
+    );
+    let outcomes: Vec<_> = Outcome::ALL
+        .into_iter()
+        .map(|outcome| {
+            let n = results.iter().filter(|r| r.outcome == outcome).count();
+            format!("{} {n} ({:.1}%)", outcome.name(), percent(n))
+        })
+        .collect();
+    println!("Outcomes: {}", outcomes.join(", "));
+    println!();
+
+    for (range, n) in histogram(&scores) {
+        let bar = "#".repeat((n * HISTOGRAM_WIDTH).div_ceil(games));
+        println!(
+            "{:>4}-{:<4} {bar:<HISTOGRAM_WIDTH$} {n} ({:.1}%)",
+            range.start,
+            range.end - 1,
+            percent(n),
+        );
+    }
+}
+
+/// Sorts the (sorted) scores into evenly sized bins from zero to the highest score, returning the
+/// range of scores in each bin and the number of scores in it.
+fn histogram(scores: &[usize]) -> Vec<(Range<usize>, usize)> {
+    let max = scores.last().copied().unwrap_or(0);
+    let width = (max + 1).div_ceil(HISTOGRAM_BARS);
+    (0..=max)
+        .step_by(width)
+        .map(|start| {
+            let range = start..start + width;
+            let n = scores.iter().filter(|score| range.contains(score)).count();
+            (range, n)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use snakegame::game::{CanvasSize, DeathCause, GameMode, TIME_ATTACK_LIMIT};
+
+    use super::{HISTOGRAM_BARS, Options, Outcome, histogram, simulate};
+
+    fn options(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|&arg| arg.to_owned())).unwrap()
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = options(&[
+            "--seeds",
+            "5..8",
+            "--wrap",
+            "--difficulty",
+            "hard",
+            "--size",
+            "40x25",
+            "--survival",
+            "--csv",
+        ]);
+        assert_eq!(options.seeds, 5..8);
+        assert_eq!(options.level.size, CanvasSize { w: 40, h: 25 });
+        assert!(options.config.wrap);
+        assert!(!options.config.combo);
+        assert_eq!(options.config.difficulty.name(), "HARD");
+        assert_eq!(options.config.mode, GameMode::Survival);
+        assert!(options.csv);
+
+        assert!(Options::parse(["--seeds".to_owned(), "8..5".to_owned()].into_iter()).is_err());
+        assert!(Options::parse(["--bot".to_owned(), "nobody".to_owned()].into_iter()).is_err());
+        assert!(Options::parse(["--size".to_owned(), "20x20".to_owned()].into_iter()).is_err());
+        assert!(Options::parse(["--fast".to_owned()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn simulates_games() {
+        let options = options(&[]);
+        for seed in 0..3 {
+            let result = simulate(seed, &options);
+            assert_eq!(result.seed, seed);
+            assert!(result.score >= 30);
+            assert!(result.ticks > 0);
+            assert!(matches!(
+                result.outcome,
+                Outcome::Died(DeathCause::Wall | DeathCause::Tail)
+            ));
+        }
+    }
+
+    #[test]
+    fn simulates_time_attack() {
+        let options = options(&["--time-attack"]);
+        let result = simulate(0, &options);
+        assert_eq!(result.outcome, Outcome::TimeUp);
+        assert!(result.time >= TIME_ATTACK_LIMIT);
+    }
+
+    #[test]
+    fn bins_scores() {
+        let bins = histogram(&[0, 3, 4, 9, 10, 19]);
+        assert_eq!(bins.len(), HISTOGRAM_BARS);
+        assert_eq!(bins[0], (0..2, 1));
+        assert_eq!(bins[1], (2..4, 1));
+        assert_eq!(bins[2], (4..6, 1));
+        assert_eq!(bins[9], (18..20, 1));
+        assert_eq!(bins.iter().map(|&(_, n)| n).sum::<usize>(), 6);
+
+        assert_eq!(histogram(&[0, 0]), [(0..1, 2)]);
+    }
+}
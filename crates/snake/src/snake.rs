@@ -1,277 +1,407 @@
-//! Main game logic for Snake.
+//! Main game loop for Snake.
 //!
-//! This module contains the main game logic for the game. It uses the safe, high-level API exposed
-//! by the other modules in this crate. I have (and continue to) endeavour to keep this module well
-//! documented and easy to understand.
+//! This module drives a [`GameState`] (which contains the rules of the game) and draws the result
+//! onto the terminal. It uses the safe, high-level API exposed by the other modules in this
+//! crate. I have (and continue to) endeavour to keep this module well documented and easy to
+//! understand.
 //!
 //! The main entry point for this module is [`game_main`] which expects that the terminal UI has
 //! already been setup. This function runs the game through to completion.
 
 use core::time::Duration;
-use std::{fs::File, io::Read as _, thread};
+use std::thread;
 
-use oca_io::{CircularBuffer, Result};
-use oca_term::{Color, Key, Pixel};
+use oca_io::{
+    Result,
+    timer::{Instant, TimerFile, TimerSpec},
+};
+use oca_term::{Color, Draw as _, Key, Pixel, Popup, SignalEvent};
 
-use crate::ui::{CANVAS_H, CANVAS_W, Coord, GameUi};
+use snakegame::{
+    controller::{self, Controller},
+    game::{Coord, Fruit, FruitKind, GameConfig, GameEvent, GameState, MAX_PLAYERS, PORTAL_PAIRS},
+    level::Level,
+    replay::Replay,
+};
 
-/// Defines the time between each movement of the snake. Over the couse of the game, this value
-/// will decrease. During this time, if a key is pressed, then we process the key event, and wait
-/// for the remainer of the time.
-pub const STARTING_STEP_TIME: Duration = Duration::from_millis(140);
+use crate::{ghost::Ghost, ui::GameUi};
 
-/// Defines the starting length of the snake. Note that the snake does not actualy start at this
-/// length, but slowly expands out of a single point.
-pub const STARTING_LENGTH: usize = 7;
+const PAUSED_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/paused.txt"));
 
-/// Defines the starting locations for the fruits. At the beginning of the game, we do not choose
-/// random locations for the fruits, instead we create an 'X' pattern (from this constant).
-/// Throughout the game, the number of fruits is _always_ equal to the length of this array.
-pub const FOOD_LOCATIONS: [(u16, u16); 5] = [(18, 6), (18, 12), (24, 6), (24, 12), (21, 9)];
+/// The colour of each snake; the first player is green and the second is blue.
+pub const SNAKE_COLORS: [Color; MAX_PLAYERS] = [Color::Green, Color::Blue];
 
-/// Defines where the snake begins on the canvas. This is defined in terms of [`CANVAS_H`], as we
-/// calculate from the vertical center of the screen. Note that the snake starts here as a single
-/// point and "grows" outwards from this point.
-pub const STARTING_POS: Coord = Coord {
-    x: 3,
-    y: CANVAS_H / 2,
-};
+/// The colour of each pair of portals. Both ends of a pair are the same colour.
+const PORTAL_COLORS: [Color; PORTAL_PAIRS] = [Color::Blue, Color::Red];
 
 /// Main entry point for the game logic.
 ///
-/// Returns [`None`] if the game exits because of a user action (Ctrl-C). Otherwise, returns
-/// `Some(score)`.
-pub fn game_main(ui: &mut GameUi) -> Result<Option<usize>> {
-    // Open /dev/urandom, a fast source of entropy on Linux systems.
-    let mut rng = File::open("/dev/urandom").unwrap();
-
-    // -- snake state --
-    // Initialize the snake's initial head position.
-    let mut head = STARTING_POS;
-    // We face towards the rest of the canvas, that is, rightwards.
-    let mut direction = Direction::Right;
-    // The tail starts out being empty, we add to it and trim it to keep it less than `len`.
-    let mut tail = CircularBuffer::<Coord, { (CANVAS_W * CANVAS_H) as usize }>::new();
-    // Initialize the bitboard that we use to determine valid locations for placing fruits. We take
-    // the number of game cells, divided by size of each value (64 bits). Note also that division
-    // rounds down, so we have to add another u64 (which will only be partly filled).
-    let mut bitboard = [0u64; (CANVAS_W * CANVAS_H) as usize / 64 + 1];
-    // Initialize the current step time to `STARTING_STEP_TIME`.
-    let mut step_time = STARTING_STEP_TIME;
-    // Initialize the snake's length to the starting length.
-    let mut len = STARTING_LENGTH;
-
-    // Initialize the fruits from the locations in `FOOD_LOCATIONS`.
-    for (x, y) in FOOD_LOCATIONS {
-        let coord = Coord { x, y };
-
-        // Plot the fruit on the canvas.
-        set_bb(&mut bitboard, coord, true);
-        ui.draw_canvas(coord, Pixel::new(Color::Yellow, true))?;
+/// The `seed` determines where fruits are placed; the same seed and the same key presses always
+/// produce the same game. The `config` chooses the rules, and the `level` chooses the layout of
+/// the canvas (and must already fit the UI's canvas; see [`Level::fit`]). Each snake is steered
+/// by its own [`Controller`], which is given every key that is pressed (except the pause keys).
+/// Every finished game is saved as a [`Replay`], and the game races against the `ghost` of a
+/// previous game (if there is one; see [`Ghost::find`]).
+///
+/// Returns [`None`] if the game exits because of a user action (Ctrl-C). Otherwise, returns the
+/// finished game.
+pub fn game_main(
+    ui: &mut GameUi,
+    seed: u64,
+    config: GameConfig,
+    level: &Level,
+    controllers: &mut [Box<dyn Controller + '_>],
+    mut ghost: Option<Ghost>,
+) -> Result<Option<GameState>> {
+    assert_eq!(controllers.len(), config.players());
+    assert_eq!(level.size, ui.size());
+
+    let mut state = GameState::new(seed, config, level);
+    let mut replay = Replay::new(seed, config, level);
+    draw_initial(ui, &state)?;
+    if let Some(ghost) = &ghost {
+        ghost.draw(ui)?;
     }
 
-    loop {
-        // Put the head into the tail, and mark it as occupied on the bitboard.
-        tail.push(head);
-        set_bb(&mut bitboard, head, true);
-
-        // If the tail is longer than the snake's length, trim it.
-        if tail.len() > len {
-            let coord = tail.pop().unwrap();
+    // The game is driven by a clock which ticks every `step_time`. Because the clock runs on its
+    // own, the time spent drawing doesn't slow the game down.
+    let mut clock = TimerFile::new()?;
+    start_clock(&mut clock, state.step_time())?;
 
-            set_bb(&mut bitboard, coord, false);
-            ui.draw_canvas(coord, Pixel::Clear)?;
-        }
-
-        // Draw the snake's head onto the screen.
-        ui.draw_canvas(head, Pixel::new(Color::Green, true))?;
-
-        // Sleep for the current step time, so that the snake doesn't move instantly.
+    'game: loop {
+        // Sleep until something happens: a key press, a signal, or the next tick of the clock.
         ui.flush()?;
-        thread::sleep(step_time);
+        let ticked = ui.term().wait_with(clock.as_file(), None)?;
+
+        // Handle keys as soon as they arrive, offering each one to the controllers in turn until
+        // one of them takes it.
+        let mut wants_pause = false;
+        for key in ui.term().key_iter() {
+            if is_pause_key(key) {
+                wants_pause = true;
+                break;
+            }
 
-        // Check for keys, but don't wait for anything (we've already waited).
-        if let Some(dir) = ui
-            .term()
-            .key_iter()
-            .find_map(|k| direction.change_from_key(k))
-        {
-            // If we have processed a direction key, then update the direction accordingly.
-            direction = dir;
+            for (snake, controller) in controllers.iter_mut().enumerate() {
+                if controller.key(&state, snake, key) {
+                    break;
+                }
+            }
         }
 
-        // Actually move the snake's head position, checking to see if we have hit a wall.
-        let old_pos = head;
-        match direction {
-            Direction::Up if head.y > 0 => head.y -= 1,
-            Direction::Down if head.y < CANVAS_H - 1 => head.y += 1,
-            Direction::Right if head.x < CANVAS_W - 1 => head.x += 1,
-            Direction::Left if head.x > 0 => head.x -= 1,
-            _ => break,
+        // If the player asked to pause, then wait here until they resume. The clock is restarted
+        // afterwards, so the snake doesn't move while the player is away.
+        if wants_pause {
+            if pause(ui)? {
+                return Ok(None);
+            }
+            start_clock(&mut clock, state.step_time())?;
+            continue;
         }
 
-        // Check if we have encountered *something*, we'll find out what it is below.
-        if get_bb(&bitboard, head) {
-            // If we have hit our own tail, then we die...
-            if tail.iter().any(|h| h == head) {
-                // Make sure to reset the head position back to where it was, otherwise the animation
-                // mucks up.
-                head = old_pos;
-                break;
+        // If we weren't woken up by the clock, then it was probably a signal. After the terminal
+        // is resized, the clock is restarted, in case we had to wait for it to be enlarged.
+        if !ticked {
+            match ui.process_signals()? {
+                SignalEvent::None => (),
+                SignalEvent::Exit => return Ok(None),
+                SignalEvent::Resize => start_clock(&mut clock, state.step_time())?,
             }
+            continue;
+        }
 
-            // ...otherwise, we have eaten a fruit.
-            len += 1;
-
-            // Speed the snake up a little.
-            step_time -= Duration::from_micros(500);
-
-            // Generate another fruit to replace that one we just ate. Note that we needn't remove
-            // fruit from the bitboard because we ate it and will "digest" it (the normal snake
-            // code will remove it).
-            if !gen_fruit(&mut rng, ui, &mut bitboard)? {
-                // If we could not find a location for the fruit, then the user has won and we
-                // return a score of `999`.
-                return Ok(Some(999));
+        // Advance the game by one step (remembering what we did for the replay), and draw
+        // everything that happened. If drawing took longer than a tick, then the clock will have
+        // ticked more than once, and we catch up so that the game keeps to time.
+        let step_time = state.step_time();
+        for _ in 0..clock.read()? {
+            let inputs = controller::inputs(controllers, &state);
+            replay.record(&inputs);
+            let events = state.step(&inputs);
+            draw_events(ui, &state, &events)?;
+
+            // The ghost moves in lockstep with the real game, until its own game is over.
+            if let Some(g) = &mut ghost
+                && !g.step(ui)?
+            {
+                ghost = None;
             }
 
-            // Tell the game's UI that we have a new score, this updates the leaderboard
-            // statistics panel.
-            ui.update_score(len - STARTING_LENGTH)?;
+            if state.is_finished() {
+                break 'game;
+            }
         }
 
-        // Draw the previous head position as the tail colour.
-        ui.draw_canvas(old_pos, Pixel::new(Color::Green, false))?;
+        // The snake speeds up as it eats, so the clock needs to tick faster.
+        if state.step_time() != step_time {
+            start_clock(&mut clock, state.step_time())?;
+        }
 
         // Update the game's UI, currently just the leaderboard and stats panel. This function also
-        // checks for SIGINT and SIGTERM, and if one of these signals is received, then we will
-        // exit here.
-        if ui.update_tick(true)? {
-            return Ok(None);
+        // checks for signals: if SIGINT or SIGTERM is received, then we will exit here.
+        match ui.update_tick(true)? {
+            SignalEvent::None => (),
+            SignalEvent::Exit => return Ok(None),
+            SignalEvent::Resize => start_clock(&mut clock, state.step_time())?,
         }
     }
 
-    // Do a fun little death animation.
-    for coord in tail.iter().rev().skip(1) {
-        ui.draw_canvas(coord, Pixel::new(Color::Red, false))?;
-        ui.flush()?;
-        thread::sleep(Duration::from_millis(50));
+    // Save the replay. Failing to save a replay shouldn't stop anyone from playing, so we only let
+    // the player know that this one wasn't recorded.
+    replay.finish(state.score());
+    if replay.save().is_err() {
+        ui.set_notice(Some("REPLAY NOT SAVED"))?;
+    }
+
+    // The ghost would only get in the way of the death animation.
+    if let Some(ghost) = &ghost {
+        ghost.erase(ui)?;
     }
-    thread::sleep(Duration::from_millis(150));
-    ui.draw_canvas(head, Pixel::new(Color::Red, true))?;
-    ui.flush()?;
-    thread::sleep(Duration::from_millis(500));
 
-    // Return the score, calculated as the difference between the initial and current length.
-    Ok(Some(len - STARTING_LENGTH))
+    death_animation(ui, &state)?;
+    Ok(Some(state))
 }
 
-/// Creates a fruit at a random position on the canvas, accounting for other fruits and the snake.
-///
-/// The naive approach to generating fruits is simple:
-/// 1. Choose a random location.
-/// 2. If it is not free, then repeat 1.
-/// 3. Place the fruit on the canvas.
-///
-/// This approach has a significant issue. When there are few free squares left (that is, when the
-/// snake is very long), a possibly infinite number of random numbers might be generated. In other
-/// words, there is no fixed upper time bound for this algorithm.
-///
-/// Instead, a better algorithm is used that is O(n) on the size of the canvas:
-/// 1. Calculate the number of free squares.
-/// 2. Generate a random number between 0 and the number of free squares.
-/// 3. Map the generated index onto the canvas (we iterate over the whole canvas, and only
-///    increment on free squares).
-/// 4. Place the fruit on the canvas.
+/// (Re)starts the game clock, so that its next tick is `step_time` from now, and it then ticks
+/// every `step_time`. Any ticks that haven't been read yet are discarded.
+fn start_clock(clock: &mut TimerFile, step_time: Duration) -> Result<()> {
+    clock.set(&TimerSpec::new(Some(step_time), Some(step_time)))
+}
+
+/// Checks whether a key should pause (or resume) the game.
+const fn is_pause_key(key: Key) -> bool {
+    matches!(key, Key::Char(b'p' | b' '))
+}
+
+/// Pauses the game, showing a popup over the canvas until the player resumes.
 ///
-/// This function returns a boolean that indicates whether a fruit was able to be placed. If the
-/// return value is `false`, then no valid location was found, and thus, the player has beaten the
-/// game (they have filled the screen).
-fn gen_fruit(rng: &mut File, ui: &mut GameUi, bitboard: &mut [u64]) -> Result<bool> {
-    // Read eight bytes (a u64) into a buffer.
-    let mut rand = [0u8; 8];
-    rng.read_exact(&mut rand).unwrap();
-    let rand = usize::from_le_bytes(rand);
-
-    // Calculate how many filled and free squares there are.
-    let filled = bitboard.iter().map(|x| x.count_ones()).sum::<u32>() as usize;
-    let free = (CANVAS_W as usize * CANVAS_H as usize) - filled + 1;
-    // Calculate our target square based on the number of free squares and our random number.
-    let target_idx = rand % free;
-
-    let mut idx = 0;
-    let (mut fx, mut fy) = (u16::MAX, u16::MAX);
-    // For each xy point on the canvas...
-    'outer: for y in 0..CANVAS_H {
-        for x in 0..CANVAS_W {
-            // We only increment our index on free squares.
-            if !get_bb(bitboard, Coord { x, y }) {
-                idx += 1;
-            }
+/// The stats panel's timer is stopped while paused, and the canvas underneath the popup is
+/// restored afterwards. The popup is redrawn if the terminal is resized. Returns `true` if the
+/// game should exit (because of SIGINT or SIGTERM).
+fn pause(ui: &mut GameUi) -> Result<bool> {
+    ui.pause_stats()?;
+
+    let popup = Popup::new(PAUSED_TEXT);
+    let mut pos = ui.draw_centered(&popup, false)?;
+    ui.flush()?;
+
+    let exit = loop {
+        // We wake up regularly (rather than blocking) so that we can keep the leaderboard updated
+        // and notice signals.
+        if ui
+            .term()
+            .get_key_timeout(Some(Duration::from_millis(100)), is_pause_key)?
+            .is_some()
+        {
+            break false;
+        }
 
-            // If we have made it to the target index, then exit.
-            if idx >= target_idx {
-                fx = x;
-                fy = y;
-                break 'outer;
+        match ui.update_tick(false)? {
+            SignalEvent::None => (),
+            SignalEvent::Exit => break true,
+            SignalEvent::Resize => {
+                pos = ui.draw_centered(&popup, false)?;
+                ui.flush()?;
             }
         }
+    };
+
+    // Remove the popup and redraw any part of the canvas that it was covering.
+    ui.clear_centered(&popup, pos)?;
+    ui.redraw_covered(pos, (&popup).size())?;
+
+    ui.resume_stats()?;
+    Ok(exit)
+}
+
+/// Returns every non-empty cell of the canvas, along with how it should be drawn.
+pub fn canvas_pixels(state: &GameState) -> impl Iterator<Item = (Coord, Pixel)> + '_ {
+    let walls = state
+        .walls()
+        .iter()
+        .map(|&coord| (coord, Pixel::new(Color::White, false)));
+    let portals = state
+        .portals()
+        .iter()
+        .zip(PORTAL_COLORS)
+        .flat_map(|(portal, color)| {
+            [portal.0, portal.1].map(|coord| (coord, Pixel::new(color, true)))
+        });
+    let fruits = state
+        .fruits()
+        .iter()
+        .map(|&fruit| (fruit.coord, fruit_pixel(fruit)));
+    let snakes = state
+        .snakes()
+        .iter()
+        .zip(SNAKE_COLORS)
+        .flat_map(|(snake, color)| {
+            let tail = snake
+                .tail()
+                .filter(move |&coord| coord != snake.head())
+                .map(move |coord| (coord, Pixel::new(color, false)));
+            tail.chain([(snake.head(), Pixel::new(color, true))])
+        });
+
+    walls.chain(portals).chain(fruits).chain(snakes)
+}
+
+/// Returns how a fruit should be drawn. Each kind of fruit has its own colour.
+const fn fruit_pixel(fruit: Fruit) -> Pixel {
+    match fruit.kind {
+        FruitKind::Normal => Pixel::new(Color::Yellow, true),
+        FruitKind::Golden => Pixel::new(Color::Yellow, false),
+        FruitKind::Shrink => Pixel::new(Color::Magenta, true),
+        FruitKind::SlowDown => Pixel::new(Color::Cyan, true),
     }
+}
 
-    // If we do not find a location for the new fruit, then the player has entirely filled the
-    // screen; they have won. Report this to the caller.
-    if fx == u16::MAX {
-        return Ok(false);
+/// Draws the level's walls, any portals, the initial fruits and the snakes' heads onto the canvas.
+pub fn draw_initial(ui: &mut GameUi, state: &GameState) -> Result<()> {
+    // Draw the walls, which stay on the canvas for the whole game.
+    for &coord in state.walls() {
+        ui.draw_canvas(coord, Pixel::new(Color::White, false))?;
     }
 
-    // Mark our new fruit's location on the bitboard and draw the fruit to the screen.
-    let coord = Coord { x: fx, y: fy };
-    set_bb(bitboard, coord, true);
-    ui.draw_canvas(coord, Pixel::new(Color::Yellow, true))?;
+    // Draw the portals, which also stay on the canvas for the whole game.
+    for (portal, color) in state.portals().iter().zip(PORTAL_COLORS) {
+        ui.draw_canvas(portal.0, Pixel::new(color, true))?;
+        ui.draw_canvas(portal.1, Pixel::new(color, true))?;
+    }
 
-    Ok(true)
-}
+    // Plot the initial fruits on the canvas.
+    for &fruit in state.fruits() {
+        ui.draw_canvas(fruit.coord, fruit_pixel(fruit))?;
+    }
 
-/// Mark a coordinate on the bitboard as either occupied or unoccupied.
-fn set_bb(bitboard: &mut [u64], coord: Coord, value: bool) {
-    // Turn the 2d coordinate into a flat index.
-    let idx = coord.as_idx();
-    // Use magic bitwise operators to set/unset.
-    if value {
-        bitboard[idx / 64] |= 0b1 << (idx % 64);
-    } else {
-        bitboard[idx / 64] &= !(0b1 << (idx % 64));
+    // Draw the snakes' heads onto the screen.
+    for (snake, color) in state.snakes().iter().zip(SNAKE_COLORS) {
+        ui.draw_canvas(snake.head(), Pixel::new(color, true))?;
     }
+    Ok(())
 }
 
-/// Check whether a coordinate on the bitboard is occupied or unoccupied.
-const fn get_bb(bitboard: &[u64], coord: Coord) -> bool {
-    // Turn the 2d coordinate into a flat index.
-    let idx = coord.as_idx();
-    // Use magic bitwise operators to check if the bit is marked as occupied.
-    bitboard[idx / 64] & (0b1 << (idx % 64)) != 0
+/// Returns the cells of the canvas that a [`GameEvent`] changed, along with how they should now
+/// be drawn.
+pub fn event_pixels(event: GameEvent) -> impl Iterator<Item = (Coord, Pixel)> {
+    let pixels = match event {
+        // Draw the previous head position as the tail colour, and the new head.
+        GameEvent::Moved { snake, old, head } => [
+            Some((old, Pixel::new(SNAKE_COLORS[snake], false))),
+            Some((head, Pixel::new(SNAKE_COLORS[snake], true))),
+        ],
+        GameEvent::Trimmed(coord) | GameEvent::FruitExpired(coord) => {
+            [Some((coord, Pixel::Clear)), None]
+        }
+        GameEvent::NewFruit(fruit) => [Some((fruit.coord, fruit_pixel(fruit))), None],
+        GameEvent::NewWall(coord) => [Some((coord, Pixel::new(Color::White, false))), None],
+        // The snake's old tail has been trimmed, so only its new head is left.
+        GameEvent::Crashed { snake, head, .. } => {
+            [Some((head, Pixel::new(SNAKE_COLORS[snake], true))), None]
+        }
+        _ => [None, None],
+    };
+    pixels.into_iter().flatten()
 }
 
-/// Enumeration representing the four possible directions that the snake can be moving in.
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub enum Direction {
-    Up,
-    Down,
-    Right,
-    Left,
+/// Draws the events from a single [`GameState::step`] onto the canvas, and updates the stats
+/// panel and leaderboard.
+pub fn draw_events(ui: &mut GameUi, state: &GameState, events: &[GameEvent]) -> Result<()> {
+    for &event in events {
+        for (coord, pixel) in event_pixels(event) {
+            ui.draw_canvas(coord, pixel)?;
+        }
+
+        match event {
+            GameEvent::AteFruit { snake, .. } | GameEvent::Crashed { snake, .. } => {
+                // Tell the game's UI that we have a new score, this updates the leaderboard
+                // statistics panel.
+                ui.update_score(snake, state.snakes()[snake].score())?;
+            }
+            GameEvent::Multiplier { snake, multiplier } => {
+                ui.update_multiplier(snake, multiplier)?;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
 }
 
-impl Direction {
-    /// Convert a keypress event into a direction for the snake, checking that the snake isn't
-    /// doubling back on itself or continuing in the same direction (the latter improves input
-    /// "feel").
-    pub const fn change_from_key(self, key: Key) -> Option<Self> {
-        Some(match (self, key) {
-            (Self::Left | Self::Right, Key::Up | Key::Char(b'w')) => Self::Up,
-            (Self::Left | Self::Right, Key::Down | Key::Char(b's')) => Self::Down,
-            (Self::Up | Self::Down, Key::Right | Key::Char(b'd')) => Self::Right,
-            (Self::Up | Self::Down, Key::Left | Key::Char(b'a')) => Self::Left,
-            _ => return None,
-        })
+/// Does a fun little death animation, for each snake that died.
+///
+/// If the player has won (filled the screen), then there is no animation.
+pub fn death_animation(ui: &mut GameUi, state: &GameState) -> Result<()> {
+    for snake in state.snakes().iter().filter(|s| !s.is_alive()) {
+        for coord in snake.tail().rev().skip(1) {
+            ui.draw_canvas(coord, Pixel::new(Color::Red, false))?;
+            ui.flush()?;
+            thread::sleep(Duration::from_millis(50));
+        }
+        thread::sleep(Duration::from_millis(150));
+        ui.draw_canvas(snake.head(), Pixel::new(Color::Red, true))?;
+        ui.flush()?;
+        thread::sleep(Duration::from_millis(500));
+    }
+    Ok(())
+}
+
+/// Plays back a [`Replay`] on the canvas. The `level` must be the one named in the replay, and is
+/// stretched to the replay's canvas size (see [`Level::fit`]), which must be the size of the UI's
+/// canvas.
+///
+/// While watching, the following keys are available:
+/// - `P` pauses and resumes playback;
+/// - `N` steps forward a single tick (while paused); and
+/// - `1`, `2` and `4` set the playback speed.
+///
+/// Returns [`None`] if playback was stopped because of a user action (Ctrl-C). Otherwise, returns
+/// the finished game.
+pub fn playback(ui: &mut GameUi, replay: &Replay, level: &Level) -> Result<Option<GameState>> {
+    assert_eq!(replay.size, ui.size());
+    let mut state = GameState::new(replay.seed, replay.config, &level.fit(replay.size));
+    let mut controllers: Vec<Box<dyn Controller>> = (0..replay.config.players())
+        .map(|snake| Box::new(replay.controller(snake)) as Box<dyn Controller>)
+        .collect();
+    let mut speed = 1;
+    let mut paused = false;
+
+    draw_initial(ui, &state)?;
+
+    for _ in 0..replay.ticks {
+        ui.flush()?;
+
+        // Wait until the next tick is due, handling the playback controls in the meantime.
+        let next_tick = Instant::now()? + state.step_time() / speed;
+        loop {
+            // While paused, we still wake up regularly to check for signals.
+            let timeout = if paused {
+                Duration::from_millis(100)
+            } else {
+                next_tick - Instant::now()?
+            };
+
+            match ui.term().get_key_timeout(Some(timeout), |k| {
+                matches!(k, Key::Char(b'p' | b'n' | b'1' | b'2' | b'4'))
+            })? {
+                Some(Key::Char(b'p')) => paused = !paused,
+                Some(Key::Char(b'n')) if paused => break,
+                Some(Key::Char(ch @ (b'1' | b'2' | b'4'))) => speed = u32::from(ch - b'0'),
+                _ => (),
+            }
+
+            if ui.update_tick(false)? == SignalEvent::Exit {
+                return Ok(None);
+            }
+
+            if !paused && Instant::now()? >= next_tick {
+                break;
+            }
+        }
+
+        let events = state.step(&controller::inputs(&mut controllers, &state));
+        draw_events(ui, &state, &events)?;
     }
+
+    death_animation(ui, &state)?;
+    Ok(Some(state))
 }
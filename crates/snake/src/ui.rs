@@ -1,62 +1,314 @@
-use core::fmt;
+use core::{
+    fmt::{self, Write as _},
+    time::Duration,
+};
 
-use oca_term::{Box, CenteredStr, Clear, Draw, DrawCtx, Rect, Terminal, draw};
+use oca_term::{
+    Box, CenteredStr, Clear, Color, Draw, DrawCtx, Pixel, Popup, Rect, SignalEvent, Terminal,
+    ansi_str_len, draw,
+};
 
-use crate::leaderboard::{Leaderboard, LeaderboardUpdate};
-use oca_io::{Result, timer::Instant};
+use snakegame::game::{CanvasSize, Coord, GameConfig, GameMode, MAX_PLAYERS, TIME_ATTACK_LIMIT};
+
+use crate::leaderboard::{self, Leaderboard, LeaderboardUpdate};
+use oca_io::{Result, network::LeaderboardEntry, timer::Instant};
 
 const CREDITS_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/credits.txt"));
 const STATS_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/stats.txt"));
+const STATS_TWO_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/stats-two.txt"));
+const COMBO_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/combo.txt"));
+const COMBO_TWO_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/combo-two.txt"));
 const SNAKE_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/snake.txt"));
 const HELP_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/help.txt"));
+const ENLARGE_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/enlarge.txt"));
 #[cfg(debug_assertions)]
 const GIT_TEXT: &str = include_str!(concat!(env!("OUT_DIR"), "/git.txt"));
 
-pub const CANVAS_W: u16 = 28;
-pub const CANVAS_H: u16 = 19;
+/// How the UI is arranged around the canvas. The layout is picked from the size of the terminal
+/// (see [`Layout::ALL`]), so that the game can be played in small terminals too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// The title above the canvas, the stats panel to its left, the leaderboard to its right, and
+    /// the credits in the corner.
+    Full,
+    /// The stats panel and leaderboard side by side below the canvas, for narrow terminals.
+    Stacked,
+    /// A one-line status bar below the canvas (see [`status_bar`]), without the help text.
+    Compact,
+}
+
+impl Layout {
+    /// Every layout, from the most to the least preferred.
+    const ALL: [Self; 3] = [Self::Full, Self::Stacked, Self::Compact];
+
+    /// The room needed around the canvas, in terminal cells.
+    const fn margin(self) -> (u16, u16) {
+        match self {
+            // The smallest canvas needs a 95x33 terminal.
+            Self::Full => (39, 14),
+            // The canvas border and help text, then the leaderboard underneath.
+            Self::Stacked => (2, 19),
+            // The canvas border, then the status bar. The smallest canvas needs 58x22.
+            Self::Compact => (2, 3),
+        }
+    }
+
+    /// Returns the biggest canvas that fits in a terminal of the given size with this layout
+    /// (within [`CanvasSize::MAX`]), or [`None`] if the terminal is too small for any canvas.
+    const fn canvas_size(self, (w, h): (u16, u16)) -> Option<CanvasSize> {
+        let (mw, mh) = self.margin();
+        let Some(size) = (CanvasSize {
+            w: w.saturating_sub(mw) / 2,
+            h: h.saturating_sub(mh),
+        })
+        .clamp() else {
+            return None;
+        };
+
+        // Popups are centred on the canvas, which only lines up if its height is odd.
+        Some(CanvasSize {
+            h: size.h - (1 - size.h % 2),
+            ..size
+        })
+    }
+
+    /// Checks whether a canvas of the given size fits in a terminal of the given size with this
+    /// layout.
+    const fn fits(self, term_size: (u16, u16), size: CanvasSize) -> bool {
+        let (mw, mh) = self.margin();
+        size.w * 2 + mw <= term_size.0 && size.h + mh <= term_size.1
+    }
+
+    /// Picks a layout for a terminal of the given size, along with the size of the canvas: either
+    /// the given size, or otherwise the biggest canvas that fits.
+    fn pick(term_size: (u16, u16), size: Option<CanvasSize>) -> Option<(Self, CanvasSize)> {
+        Self::ALL.into_iter().find_map(|layout| match size {
+            Some(size) => layout.fits(term_size, size).then_some((layout, size)),
+            None => Some((layout, layout.canvas_size(term_size)?)),
+        })
+    }
+}
 
 pub struct GameUi {
     term: Terminal,
     stats: Stats,
     lb: Option<Leaderboard>,
+    config: GameConfig,
+    layout: Layout,
+    /// The size of the canvas, in game cells.
+    size: CanvasSize,
+    /// What is drawn in each cell of the canvas (indexed by [`Coord::as_idx`]), so that the canvas
+    /// can be redrawn when the terminal is resized.
+    canvas: Vec<Pixel>,
+    /// A layer underneath the canvas (indexed the same way), which only shows in cells where the
+    /// canvas is clear. It holds the ghost of a previous game (see [`crate::ghost`]).
+    ghost: Vec<Pixel>,
+    /// A warning shown in place of the help text (or in the status bar), until it is cleared.
+    notice: Option<&'static str>,
     cx: u16,
     cy: u16,
 }
 
 impl GameUi {
-    pub fn init() -> Result<Self> {
+    /// Sets up the terminal and draws the UI around the canvas.
+    ///
+    /// The canvas is the given size (for example, to watch a replay), or otherwise as big as the
+    /// terminal allows (see [`Layout::pick`]).
+    pub fn init(size: Option<CanvasSize>) -> Result<Self> {
         let mut term = Terminal::new()?;
-        let size = term.size();
-        if size.0 < 95 || size.1 < 33 {
-            term.exit_with_error("terminal is too small; (95, 33) required")
-        }
-
-        let (cx, cy) = draw_static(&mut term)?;
-
-        let stats = Stats(Instant::now()?);
-        term.draw(cx - 16, cy + 2, &stats)?;
+        let Some((layout, size)) = Layout::pick(term.size(), size) else {
+            term.exit_with_error(if size.is_some() {
+                "terminal is too small for this canvas size"
+            } else {
+                "terminal is too small; (58, 22) required"
+            })
+        };
 
-        let lb = if let Some(leaderboard) = Leaderboard::init() {
-            let mut leaderboard = leaderboard?;
-            term.draw(cx + (CANVAS_W * 2) + 4, cy, &mut leaderboard)?;
-            Some(leaderboard)
-        } else {
-            None
+        let stats = Stats {
+            start: Instant::now()?,
+            paused: None,
+            time: Duration::ZERO,
+            limit: None,
+            scores: [0; MAX_PLAYERS],
+            multipliers: [1; MAX_PLAYERS],
+            two_player: false,
+            combo: false,
+            attached: layout == Layout::Full,
         };
+        let lb = Leaderboard::init().transpose()?;
 
-        Ok(Self {
+        let mut ui = Self {
             term,
             stats,
             lb,
-            cx,
-            cy,
-        })
+            config: GameConfig::default(),
+            layout,
+            size,
+            canvas: vec![Pixel::Clear; size.cells()],
+            ghost: vec![Pixel::Clear; size.cells()],
+            notice: None,
+            cx: 0,
+            cy: 0,
+        };
+        ui.draw_all()?;
+        Ok(ui)
+    }
+
+    /// Returns the size of the canvas.
+    pub const fn size(&self) -> CanvasSize {
+        self.size
+    }
+
+    /// Shows which rules the next game will be played with. The canvas border is drawn in a
+    /// different colour for wrap-around walls, the stats panel shows a score (and combo
+    /// multiplier) for each player, and the leaderboard switches to the one for these rules.
+    pub fn set_config(&mut self, config: GameConfig) -> Result<()> {
+        if config.wrap != self.config.wrap {
+            self.draw_border(config.wrap)?;
+        }
+        if config.two_player != self.config.two_player || config.combo != self.config.combo {
+            self.stats.two_player = config.two_player;
+            self.stats.combo = config.combo;
+            if let Some((x, y)) = self.stats_pos() {
+                // The combo line makes the panel taller, so clear the old panel first.
+                let (w, h) = (&self.stats).size();
+                self.term.draw(x, y, Clear(w + 2, h + 2))?;
+                self.term.draw(x, y, &self.stats)?;
+            } else {
+                self.draw_status_bar()?;
+            }
+        }
+        if config.mode != self.config.mode {
+            // The time counts down from the limit in time attack.
+            self.stats.limit = (config.mode == GameMode::TimeAttack).then_some(TIME_ATTACK_LIMIT);
+            self.config = config;
+            self.reset_stats()?;
+        }
+        self.config = config;
+
+        let (board, title) = leaderboard::board_for(config);
+        self.update_lb(LeaderboardUpdate::Board(board, title))
+    }
+
+    /// Redraws the outline of the canvas, in a different colour for wrap-around walls.
+    fn draw_border(&mut self, wrap: bool) -> Result<()> {
+        // 39 is the default foreground colour.
+        let color = if wrap {
+            Color::Cyan.fg_bright()
+        } else {
+            *b"39"
+        };
+        let color = Color::to_str(&color);
+        self.term.draw(self.cx, self.cy, format!("\x1B[{color}m"))?;
+        self.term.draw(
+            self.cx,
+            self.cy,
+            canvas_border(self.size, self.layout).with_fastdraw(),
+        )?;
+        self.term.draw(self.cx, self.cy, "\x1B[0m")
+    }
+
+    /// Draws everything except the canvas's contents: the static elements (see [`draw_static`]),
+    /// and the stats panel and leaderboard (or the status bar).
+    fn draw_all(&mut self) -> Result<()> {
+        // The output buffer is small, so it is flushed after each part is drawn.
+        (self.cx, self.cy) = draw_static(&mut self.term, self.size, self.layout)?;
+        self.term.flush()?;
+        if self.config.wrap {
+            self.draw_border(true)?;
+        }
+
+        if let Some((x, y)) = self.stats_pos() {
+            self.term.draw(x, y, &self.stats)?;
+        }
+        if let Some((x, y)) = self.lb_pos()
+            && let Some(lb) = &mut self.lb
+        {
+            self.term.draw(x, y, lb)?;
+        }
+        if self.layout == Layout::Compact || self.notice.is_some() {
+            self.draw_help()?;
+        }
+        self.term.flush()
+    }
+
+    /// Shows a warning underneath the canvas in place of the help text (or in the status bar, in
+    /// the compact layout), or brings the help text back if `notice` is [`None`].
+    pub fn set_notice(&mut self, notice: Option<&'static str>) -> Result<()> {
+        if notice == self.notice {
+            return Ok(());
+        }
+        self.notice = notice;
+        self.draw_help()
+    }
+
+    /// Redraws the help text underneath the canvas, or the notice instead of it if there is one.
+    fn draw_help(&mut self) -> Result<()> {
+        if self.layout == Layout::Compact {
+            return self.draw_status_bar();
+        }
+
+        let (x, y, width) = (self.cx + 1, self.cy + self.size.h + 2, self.size.w * 2);
+        self.term.draw(x, y, Clear(width, 2))?;
+        match self.notice {
+            Some(notice) => {
+                let w = ansi_str_len(notice);
+                self.term
+                    .draw(x + (width - w) / 2, y, format!("\x1B[1;91m{notice}\x1B[0m"))
+            }
+            None => self
+                .term
+                .draw_centered(CenteredStr(HELP_TEXT), Rect::new(x, y, width, 2))
+                .map(|_| ()),
+        }
+    }
+
+    /// Returns where the stats panel is drawn, or [`None`] if it isn't shown in this layout.
+    const fn stats_pos(&self) -> Option<(u16, u16)> {
+        match self.layout {
+            Layout::Full => Some((self.cx - 16, self.cy + 2)),
+            Layout::Stacked => Some((self.cx, self.cy + self.size.h + 5)),
+            Layout::Compact => None,
+        }
+    }
+
+    /// Returns where the leaderboard is drawn, or [`None`] if it isn't shown in this layout.
+    const fn lb_pos(&self) -> Option<(u16, u16)> {
+        match self.layout {
+            Layout::Full => Some((self.cx + (self.size.w * 2) + 4, self.cy)),
+            Layout::Stacked => Some((self.cx + (self.size.w * 2) - 13, self.cy + self.size.h + 5)),
+            Layout::Compact => None,
+        }
+    }
+
+    /// Redraws the status bar underneath the canvas, in the compact layout.
+    fn draw_status_bar(&mut self) -> Result<()> {
+        let width = (self.size.w * 2) + 2;
+        let y = self.cy + self.size.h + 2;
+        self.term.draw(self.cx, y, Clear(width, 1))?;
+
+        // The leaderboard is left out if there isn't room for it, and a notice takes its place.
+        let mut text = match self.notice {
+            Some(notice) => format!(
+                "{}  \x1B[1;91m{notice}\x1B[0m",
+                status_bar(&self.stats, None)?
+            ),
+            None => status_bar(&self.stats, self.lb.as_ref())?,
+        };
+        if ansi_str_len(&text) > width {
+            text = match self.notice {
+                Some(notice) => format!("\x1B[1;91m{notice}\x1B[0m"),
+                None => status_bar(&self.stats, None)?,
+            };
+        }
+        let w = ansi_str_len(&text);
+        self.term.draw(self.cx + (width - w) / 2, y, text)
     }
 
     pub fn draw_centered(&mut self, object: impl Draw, hoff: bool) -> Result<(u16, u16)> {
         self.term.draw_centered_hoff(
             object,
-            Rect::new(self.cx, self.cy, (CANVAS_W * 2) + 2, CANVAS_H + 2),
+            Rect::new(self.cx, self.cy, (self.size.w * 2) + 2, self.size.h + 2),
             hoff,
         )
     }
@@ -67,35 +319,187 @@ impl GameUi {
         self.term.draw(pos.0, pos.1, Clear(w, h))
     }
 
-    pub fn draw_canvas(&mut self, coord: Coord, object: impl Draw) -> Result<()> {
+    pub fn draw_canvas(&mut self, coord: Coord, pixel: Pixel) -> Result<()> {
+        self.canvas[coord.as_idx(self.size)] = pixel;
+        self.draw_cell(coord)
+    }
+
+    /// Draws a pixel onto the ghost layer, underneath the canvas. The pixel is only shown while
+    /// the canvas is clear in that cell, so drawing (or clearing) the ghost never damages anything
+    /// on the canvas.
+    pub fn draw_ghost(&mut self, coord: Coord, pixel: Pixel) -> Result<()> {
+        let idx = coord.as_idx(self.size);
+        self.ghost[idx] = pixel;
+        if matches!(self.canvas[idx], Pixel::Clear) {
+            self.draw_cell(coord)?;
+        }
+        Ok(())
+    }
+
+    /// Redraws every canvas cell that is (at least partly) covered by an object of the given size,
+    /// drawn at `pos`. This restores the canvas after a popup has been cleared.
+    pub fn redraw_covered(&mut self, pos: (u16, u16), size: (u16, u16)) -> Result<()> {
+        for coord in self.size.coords() {
+            if self.canvas_covered(coord, pos, size) {
+                self.draw_cell(coord)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws whatever is shown in a canvas cell: the canvas, or the ghost layer where the canvas
+    /// is clear.
+    fn draw_cell(&mut self, coord: Coord) -> Result<()> {
+        let idx = coord.as_idx(self.size);
+        let pixel = match self.canvas[idx] {
+            Pixel::Clear => self.ghost[idx],
+            pixel => pixel,
+        };
         self.term
-            .draw(self.cx + (coord.x * 2) + 1, self.cy + coord.y + 1, object)
+            .draw(self.cx + (coord.x * 2) + 1, self.cy + coord.y + 1, pixel)
+    }
+
+    /// Checks whether the given canvas cell is (at least partly) covered by an object of the given
+    /// size, drawn at `pos` (for example, by [`Self::draw_centered`]).
+    pub const fn canvas_covered(&self, coord: Coord, pos: (u16, u16), size: (u16, u16)) -> bool {
+        let (x, y) = (self.cx + (coord.x * 2) + 1, self.cy + coord.y + 1);
+        x + 2 > pos.0 && x < pos.0 + size.0 && y >= pos.1 && y < pos.1 + size.1
+    }
+
+    /// Shows a player's new score. Only the first player's score is shown on the leaderboard.
+    pub fn update_score(&mut self, player: usize, score: usize) -> Result<()> {
+        self.update_stats(StatsUpdate::Score(player, score))?;
+        if player == 0 {
+            self.update_lb(LeaderboardUpdate::Score(score as u16))?;
+        }
+        Ok(())
     }
 
-    pub fn update_score(&mut self, score: usize) -> Result<()> {
-        self.update_stats(StatsUpdate::Score(score))?;
-        self.update_lb(LeaderboardUpdate::Score(score.try_into().unwrap()))
+    /// Shows a player's new combo multiplier.
+    pub fn update_multiplier(&mut self, player: usize, multiplier: usize) -> Result<()> {
+        self.update_stats(StatsUpdate::Multiplier(player, multiplier))
     }
 
-    pub fn update_tick(&mut self, stats: bool) -> Result<bool> {
+    pub fn update_tick(&mut self, stats: bool) -> Result<SignalEvent> {
         if stats {
-            self.term
-                .update(self.cx - 16, self.cy + 2, &self.stats, StatsUpdate::Time)?;
+            self.update_stats(StatsUpdate::Time)?;
         }
 
         self.update_lb(LeaderboardUpdate::Network(false, false))?;
-        self.term.process_signals()
+        self.process_signals()
+    }
+
+    /// Handles a signal, if one has been received. When the terminal is resized, the whole UI is
+    /// redrawn to fit (see [`Self::relayout`]), and the caller should then redraw any popup that it
+    /// is showing.
+    pub fn process_signals(&mut self) -> Result<SignalEvent> {
+        match self.term.process_signals()? {
+            SignalEvent::Resize => self.relayout(),
+            event => Ok(event),
+        }
+    }
+
+    /// Redraws everything after the terminal has been resized. The canvas keeps its size (and its
+    /// contents), and is centred in the resized terminal.
+    ///
+    /// If the terminal is now too small for the canvas, then a notice is shown instead until the
+    /// terminal is enlarged, with the stats panel's timer stopped. Returns [`SignalEvent::Exit`]
+    /// if SIGINT or SIGTERM is received while waiting, and [`SignalEvent::Resize`] otherwise.
+    fn relayout(&mut self) -> Result<SignalEvent> {
+        let mut stopped_stats = false;
+        loop {
+            if let Some((layout, _)) = Layout::pick(self.term.size(), Some(self.size)) {
+                self.layout = layout;
+                self.stats.attached = layout == Layout::Full;
+                break;
+            }
+
+            if self.stats.paused.is_none() {
+                self.pause_stats()?;
+                stopped_stats = true;
+            }
+
+            // If even the notice doesn't fit, then the screen is just left empty.
+            let popup = Popup::new(ENLARGE_TEXT);
+            let (w, h) = self.term.size();
+            let (pw, ph) = (&popup).size();
+            self.term.clear()?;
+            if pw <= w && ph <= h {
+                self.term.draw((w - pw) / 2 + 1, (h - ph) / 2 + 1, &popup)?;
+            }
+            self.term.flush()?;
+
+            // Wait for the terminal to be resized again, waking up regularly to check for signals.
+            // Any keys pressed in the meantime are thrown away.
+            loop {
+                self.term
+                    .get_key_timeout(Some(Duration::from_millis(100)), |_| false)?;
+                match self.term.process_signals()? {
+                    SignalEvent::None => (),
+                    SignalEvent::Exit => return Ok(SignalEvent::Exit),
+                    SignalEvent::Resize => break,
+                }
+            }
+        }
+        if stopped_stats {
+            self.resume_stats()?;
+        }
+
+        self.term.clear()?;
+        self.draw_all()?;
+
+        for coord in self.size.coords() {
+            let idx = coord.as_idx(self.size);
+            if !matches!(
+                (self.canvas[idx], self.ghost[idx]),
+                (Pixel::Clear, Pixel::Clear)
+            ) {
+                self.draw_cell(coord)?;
+            }
+            if coord.x == self.size.w - 1 {
+                self.term.flush()?;
+            }
+        }
+
+        Ok(SignalEvent::Resize)
     }
 
     pub fn clear_canvas(&mut self) -> Result<()> {
-        self.term
-            .draw(self.cx + 1, self.cy + 1, Clear(CANVAS_W * 2, CANVAS_H))
+        self.canvas.fill(Pixel::Clear);
+        self.ghost.fill(Pixel::Clear);
+        self.term.draw(
+            self.cx + 1,
+            self.cy + 1,
+            Clear(self.size.w * 2, self.size.h),
+        )
     }
 
     pub fn reset_stats(&mut self) -> Result<()> {
-        self.stats.0 = Instant::now()?;
+        self.stats.start = Instant::now()?;
+        self.stats.paused = None;
         self.update_stats(StatsUpdate::Time)?;
-        self.update_stats(StatsUpdate::Score(0))
+        for player in 0..self.config.players() {
+            self.update_stats(StatsUpdate::Score(player, 0))?;
+            if self.config.combo {
+                self.update_stats(StatsUpdate::Multiplier(player, 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops the elapsed time in the stats panel from advancing, until [`Self::resume_stats`] is
+    /// called.
+    pub fn pause_stats(&mut self) -> Result<()> {
+        assert!(self.stats.paused.is_none());
+        self.stats.paused = Some(Instant::now()?);
+        Ok(())
+    }
+
+    /// Resumes the elapsed time in the stats panel, not counting the time spent paused.
+    pub fn resume_stats(&mut self) -> Result<()> {
+        let paused = self.stats.paused.take().unwrap();
+        self.stats.start = self.stats.start + paused.elapsed()?;
+        Ok(())
     }
 
     pub fn reset_lb(&mut self, block_lb: bool) -> Result<()> {
@@ -111,16 +515,41 @@ impl GameUi {
     }
 
     fn update_stats(&mut self, up: StatsUpdate) -> Result<()> {
-        self.term.update(self.cx - 16, self.cy + 2, &self.stats, up)
+        // Remember what is shown, so that the panel can be redrawn.
+        match up {
+            StatsUpdate::Score(player, score) => self.stats.scores[player] = score,
+            StatsUpdate::Multiplier(player, multiplier) => {
+                self.stats.multipliers[player] = multiplier;
+            }
+            StatsUpdate::Time => {
+                // While paused, the time is frozen at the moment that the game was paused.
+                let now = self.stats.paused.map_or_else(Instant::now, Ok)?;
+                let elapsed = now - self.stats.start;
+                self.stats.time = self
+                    .stats
+                    .limit
+                    .map_or(elapsed, |limit| limit.saturating_sub(elapsed));
+            }
+        }
+        match self.stats_pos() {
+            Some((x, y)) => self.term.update(x, y, &self.stats, up),
+            None => self.draw_status_bar(),
+        }
     }
 
     pub fn update_lb(&mut self, update: LeaderboardUpdate) -> Result<()> {
-        if let Some(lb) = &mut self.lb {
-            self.term
-                .update(self.cx + (CANVAS_W * 2) + 4, self.cy, lb, update)
-        } else {
-            Ok(())
+        let pos = self.lb_pos();
+        let Some(lb) = &mut self.lb else {
+            return Ok(());
+        };
+        if let Some((x, y)) = pos {
+            return self.term.update(x, y, lb, update);
         }
+
+        // The leaderboard still needs to keep up with the server while it isn't shown, so the
+        // update is drawn somewhere that nobody will see it.
+        oca_term::update(&mut String::new(), lb, 1, 1, update)?;
+        self.draw_status_bar()
     }
 
     pub fn flush(&mut self) -> Result<()> {
@@ -137,83 +566,162 @@ impl GameUi {
 /// - The SNAKE text (top center);
 /// - The canvas/play area; and
 /// - The help text (beneath canvas).
-fn draw_static(term: &mut Terminal) -> Result<(u16, u16)> {
+///
+/// Only the full layout has room for the credits, git commit and title. The compact layout leaves
+/// out the help text as well.
+fn draw_static(term: &mut Terminal, size: CanvasSize, layout: Layout) -> Result<(u16, u16)> {
     let (w, h) = term.size();
 
-    // Draw the credits text in the bottom left corner of the screen.
-    term.draw(1, h - 3, CREDITS_TEXT)?;
-
-    // Draw the git commit text in the bottom right corner of the screen, but only when compiling
-    // for debug mode.
-    #[cfg(debug_assertions)]
-    {
-        let git_width = oca_term::ansi_str_len(GIT_TEXT.split_once('\n').unwrap().0);
-        term.draw(w - git_width, h - 1, GIT_TEXT)?;
-    }
+    let (cx, cy) = if layout == Layout::Full {
+        // Draw the credits text in the bottom left corner of the screen.
+        term.draw(1, h - 3, CREDITS_TEXT)?;
 
-    // Draw the SNAKE title text in the top center of the screen.
-    term.draw_centered(SNAKE_TEXT, Rect::new(1, 1, w, 4))?;
+        // Draw the git commit text in the bottom right corner of the screen, but only when
+        // compiling for debug mode.
+        #[cfg(debug_assertions)]
+        {
+            let git_width = ansi_str_len(GIT_TEXT.split_once('\n').unwrap().0);
+            term.draw(w - git_width, h - 1, GIT_TEXT)?;
+        }
 
-    // Draw the outline of the canvas in the center of the entire screen. We use the xy values
-    // given back to calculate the position of the help text, and the leaderboard + stats panel
-    // but the latter are in other places.
-    let (cx, cy) = term.draw_centered_hoff(
-        Box::new(CANVAS_W * 2, CANVAS_H + 3).with_horz_lines(&[-2]),
-        Rect::new(1, 1, w, h),
-        true,
-    )?;
+        // Draw the SNAKE title text in the top center of the screen.
+        term.draw_centered(SNAKE_TEXT, Rect::new(1, 1, w, 4))?;
+
+        // Draw the outline of the canvas in the center of the entire screen. We use the xy values
+        // given back to calculate the position of the help text, and the leaderboard + stats
+        // panel but the latter are in other places.
+        term.draw_centered_hoff(canvas_border(size, layout), Rect::new(1, 1, w, h), true)?
+    } else {
+        // Otherwise, the canvas and everything underneath it are centred together.
+        let (mw, mh) = layout.margin();
+        let (cx, cy) = ((w - size.w * 2 - mw) / 2 + 1, (h - size.h - mh) / 2 + 1);
+        term.draw(cx, cy, canvas_border(size, layout))?;
+        (cx, cy)
+    };
 
     // Draw the help text, centered underneath the canvas.
-    term.draw_centered(
-        CenteredStr(HELP_TEXT),
-        Rect::new(cx + 1, cy + CANVAS_H + 2, CANVAS_W * 2, 2),
-    )?;
+    if layout != Layout::Compact {
+        term.draw_centered(
+            CenteredStr(HELP_TEXT),
+            Rect::new(cx + 1, cy + size.h + 2, size.w * 2, 2),
+        )?;
+    }
 
     // Return the canvas coordinates so that other UI elements can use them.
     Ok((cx, cy))
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub struct Coord {
-    pub x: u16,
-    pub y: u16,
-}
-
-impl Coord {
-    pub const fn as_idx(self) -> usize {
-        self.y as usize * CANVAS_W as usize + self.x as usize
+/// The outline of the canvas, with the help text underneath it (except in the compact layout).
+fn canvas_border(size: CanvasSize, layout: Layout) -> Box<'static> {
+    if layout == Layout::Compact {
+        Box::new(size.w * 2, size.h)
+    } else {
+        Box::new(size.w * 2, size.h + 3).with_horz_lines(&[-2])
     }
 }
 
-struct Stats(Instant);
+struct Stats {
+    /// When the current game started.
+    start: Instant,
+    /// If the game is paused, when it was paused.
+    paused: Option<Instant>,
+    /// The time that is shown: the time elapsed, or the time left if there is a time limit.
+    time: Duration,
+    /// The time limit of the game, if it is a time attack game.
+    limit: Option<Duration>,
+    /// The score that is shown for each player.
+    scores: [usize; MAX_PLAYERS],
+    /// The combo multiplier that is shown for each player.
+    multipliers: [usize; MAX_PLAYERS],
+    /// Whether to show a score for each of two players.
+    two_player: bool,
+    /// Whether to show the combo multiplier, underneath the time.
+    combo: bool,
+    /// Whether the panel is attached to the left of the canvas (in the full layout), rather than
+    /// standing on its own.
+    attached: bool,
+}
 
 impl Draw for &Stats {
     fn size(&self) -> (u16, u16) {
-        (15, 4)
+        (15, if self.combo { 5 } else { 4 })
     }
 
     fn draw<W: fmt::Write>(self, ctx: &mut DrawCtx<W>) -> Result<()> {
+        let (w, h) = self.size();
         ctx.draw(
             0,
             0,
-            Box::new(15, 4)
+            Box::new(w, h)
                 .with_horz_lines(&[1])
-                .with_corners(['┌', '┤', '└', '┤']),
+                .with_corners(if self.attached {
+                    ['┌', '┤', '└', '┤']
+                } else {
+                    ['┌', '┐', '└', '┘']
+                }),
+        )?;
+        ctx.draw(
+            2,
+            1,
+            if self.two_player {
+                STATS_TWO_TEXT
+            } else {
+                STATS_TEXT
+            },
         )?;
-        ctx.draw(2, 1, STATS_TEXT)
+
+        if self.combo {
+            ctx.draw(
+                2,
+                5,
+                if self.two_player {
+                    COMBO_TWO_TEXT
+                } else {
+                    COMBO_TEXT
+                },
+            )?;
+        }
+
+        // Fill in the values that were last shown.
+        self.update(ctx, StatsUpdate::Time)?;
+        for player in 0..if self.two_player { 2 } else { 1 } {
+            self.update(ctx, StatsUpdate::Score(player, self.scores[player]))?;
+            if self.combo {
+                self.update(
+                    ctx,
+                    StatsUpdate::Multiplier(player, self.multipliers[player]),
+                )?;
+            }
+        }
+        Ok(())
     }
 
     type Update = StatsUpdate;
     fn update<W: fmt::Write>(self, ctx: &mut DrawCtx<W>, update: Self::Update) -> Result<()> {
         match update {
-            StatsUpdate::Score(score) => {
-                ctx.goto(12, 3)?;
+            StatsUpdate::Score(player, score) => {
+                // In a two-player game, the first player's score is on the left.
+                let x = if self.two_player && player == 0 {
+                    5
+                } else {
+                    12
+                };
+                ctx.goto(x, 3)?;
                 draw!(ctx, "{score:0>3}")?;
             }
+            StatsUpdate::Multiplier(player, multiplier) => {
+                // The multipliers line up underneath the scores.
+                let x = if self.two_player && player == 0 {
+                    6
+                } else {
+                    13
+                };
+                ctx.goto(x, 5)?;
+                draw!(ctx, "{multiplier}")?;
+            }
             StatsUpdate::Time => {
-                let t = Instant::now()? - self.0;
-                let mins = t.as_secs() / 60;
-                let secs = t.as_secs() % 60;
+                let mins = self.time.as_secs() / 60;
+                let secs = self.time.as_secs() % 60;
 
                 ctx.goto(10, 4)?;
                 draw!(ctx, "{mins:0>2}:{secs:0>2}")?;
@@ -224,6 +732,75 @@ impl Draw for &Stats {
 }
 
 enum StatsUpdate {
-    Score(usize),
+    Score(usize, usize),
+    Multiplier(usize, usize),
     Time,
 }
+
+/// Returns the status bar shown underneath the canvas in the compact layout, which squeezes the
+/// stats panel (and the top entry of the leaderboard, if given) onto one line.
+fn status_bar(stats: &Stats, lb: Option<&Leaderboard>) -> Result<String> {
+    let mut text = if stats.two_player {
+        format!(
+            "\x1B[1;32mP1\x1B[0m {:0>3} \x1B[1;34mP2\x1B[0m {:0>3}",
+            stats.scores[0], stats.scores[1]
+        )
+    } else {
+        format!("\x1B[1mScore\x1B[0m {:0>3}", stats.scores[0])
+    };
+
+    let (mins, secs) = (stats.time.as_secs() / 60, stats.time.as_secs() % 60);
+    write!(text, "  \x1B[1mTime\x1B[0m {mins:0>2}:{secs:0>2}")?;
+
+    if stats.combo {
+        write!(text, "  \x1B[1mCombo\x1B[0m x{}", stats.multipliers[0])?;
+        if stats.two_player {
+            write!(text, " x{}", stats.multipliers[1])?;
+        }
+    }
+
+    if let Some(lb) = lb {
+        let LeaderboardEntry(name, score) = lb.entries[0];
+        let name = core::str::from_utf8(&name).unwrap();
+        write!(text, "  \x1B[1;34m{}\x1B[0m {name} {score:0>3}", lb.title())?;
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use snakegame::game::CanvasSize;
+
+    use super::Layout;
+
+    #[test]
+    fn picks_layout() {
+        let pick = |term_size| Layout::pick(term_size, None);
+        assert_eq!(pick((95, 33)), Some((Layout::Full, CanvasSize::MIN)));
+        assert_eq!(
+            pick((120, 45)),
+            Some((Layout::Full, CanvasSize { w: 40, h: 31 }))
+        );
+        assert_eq!(
+            pick((60, 45)),
+            Some((Layout::Stacked, CanvasSize { w: 29, h: 25 }))
+        );
+        assert_eq!(
+            pick((80, 24)),
+            Some((Layout::Compact, CanvasSize { w: 39, h: 21 }))
+        );
+        assert_eq!(pick((57, 24)), None);
+
+        // A canvas of a given size uses the best layout it fits in.
+        let size = CanvasSize { w: 40, h: 31 };
+        assert_eq!(Layout::pick((95, 33), Some(size)), None);
+        assert_eq!(
+            Layout::pick((84, 45), Some(size)),
+            Some((Layout::Compact, size))
+        );
+        assert_eq!(
+            Layout::pick((80, 24), Some(CanvasSize::MIN)),
+            Some((Layout::Compact, CanvasSize::MIN))
+        );
+    }
+}